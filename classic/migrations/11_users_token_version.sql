@@ -0,0 +1,6 @@
+-- Every token embeds the user's `token_version` at the time it was issued.
+--
+-- Bumping this column invalidates all tokens issued before, which is how logging out works
+-- given that the tokens themselves are stateless.
+alter table "users"
+    add column token_version integer not null default 0;
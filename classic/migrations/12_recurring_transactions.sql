@@ -0,0 +1,30 @@
+-- Templates for transactions that should be posted automatically on a schedule, e.g. monthly rent.
+create type recurrenceT as enum ('DAILY', 'WEEKLY', 'MONTHLY');
+
+create table "recurring_transactions" (
+    id            uuid        primary key default uuid_generate_v1mc(),
+
+    -- The amount as the client sent it, the sign is applied when the transaction is posted.
+    amount        bigint      not null,
+    metadata      json        not null,
+    tx_type       txT         not null,
+    currency      text        not null,
+
+    payer_id      uuid        not null references users(id),
+    payee_id      uuid        not null references users(id),
+    group_id      uuid        not null references groups(id),
+
+    period        recurrenceT not null,
+    paused        boolean     not null default false,
+
+    -- When the next transaction is due. It is advanced in the same database transaction that posts
+    -- the transaction, so a crash can never post the same occurrence twice.
+    next_run      timestamptz not null,
+
+    created_at    timestamptz not null default now(),
+    updated_at    timestamptz
+);
+
+create index on "recurring_transactions" (next_run) where not paused;
+
+SELECT trigger_updated_at('"recurring_transactions"');
@@ -0,0 +1,15 @@
+-- Ledgers track balances per currency: transactions only move the entries in their own currency. Entries in
+-- currencies other than the group's are created the first time a pair uses them, see `update_ledger_entries`.
+alter table "ledgers"
+    add column currency text;
+
+-- Everything recorded so far is attributed to the group's currency.
+update "ledgers" l
+set currency = g.currency
+from "groups" g
+where g.id = l.group_id;
+
+alter table "ledgers"
+    alter column currency set not null;
+
+create index on "ledgers" (group_id, this_user, other_user, currency);
@@ -0,0 +1,22 @@
+-- Categories a group defines for its transactions, e.g. for spending reports.
+create table "categories" (
+    id            uuid primary key default uuid_generate_v1mc(),
+
+    group_id      uuid not null references groups(id),
+    name          text collate "case_insensitive" not null,
+
+    created_at    timestamptz not null default now(),
+    updated_at    timestamptz,
+
+    unique (group_id, name)
+);
+
+SELECT trigger_updated_at('"categories"');
+
+-- A null `category_id` means uncategorized. Deleting a category leaves its transactions and
+-- recurring transactions uncategorized rather than deleting them.
+alter table "transactions"
+    add column category_id uuid references categories(id) on delete set null;
+
+alter table "recurring_transactions"
+    add column category_id uuid references categories(id) on delete set null;
@@ -0,0 +1,16 @@
+-- What a member may do in a group. Owners can delete the group and remove other members.
+create type groupRoleT as enum ('OWNER', 'MEMBER');
+
+alter table "user_groups"
+    add column role groupRoleT not null default 'MEMBER';
+
+-- Groups created before roles existed get their earliest member as owner, which is the creator.
+update "user_groups" ug
+set role = 'OWNER'
+where ug.id = (
+    select first.id
+    from "user_groups" first
+    where first.group_id = ug.group_id
+    order by first.created_at, first.id
+    limit 1
+);
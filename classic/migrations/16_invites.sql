@@ -0,0 +1,16 @@
+-- Single-use tokens that let whoever holds them join a group.
+--
+-- Like `password_resets`, only a SHA-256 hash of the token is stored.
+create table "invites"
+(
+    id         uuid primary key     default uuid_generate_v1mc(),
+
+    group_id   uuid        not null references groups (id) on delete cascade,
+    created_by uuid        not null references users (id) on delete cascade,
+
+    token_hash text unique not null,
+
+    expires_at timestamptz not null,
+
+    created_at timestamptz not null default now()
+);
@@ -0,0 +1,4 @@
+-- A reversal is a transaction with the opposite amount of the one it reverses, so that the
+-- mistake and its correction both stay in the history. Each transaction can be reversed once.
+alter table "transactions"
+    add column reverses_tx_id uuid unique references transactions (id);
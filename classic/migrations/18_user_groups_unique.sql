@@ -0,0 +1,12 @@
+-- A user can only be a member of a group once.
+--
+-- Drop any duplicate memberships first, keeping the owner's if there is one, otherwise the earliest.
+delete from "user_groups" ug
+using "user_groups" keep
+where keep.user_id = ug.user_id
+  and keep.group_id = ug.group_id
+  and keep.id <> ug.id
+  and (keep.role, keep.created_at, keep.id) < (ug.role, ug.created_at, ug.id);
+
+alter table "user_groups"
+    add constraint user_groups_user_id_group_id_key unique (user_id, group_id);
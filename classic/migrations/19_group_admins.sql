@@ -0,0 +1,2 @@
+-- Admins can manage a group like its owner, except for deleting it and appointing other admins.
+alter type groupRoleT add value 'ADMIN' before 'MEMBER';
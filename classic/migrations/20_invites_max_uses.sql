@@ -0,0 +1,4 @@
+-- Invites can be shared with several people, up to `max_uses` of them.
+alter table "invites"
+    add column max_uses int not null default 1 check (max_uses > 0),
+    add column uses     int not null default 0;
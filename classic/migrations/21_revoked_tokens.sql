@@ -0,0 +1,11 @@
+-- Tokens that were logged out before they expired, by their `jti` claim.
+--
+-- Rows can be deleted once `expires_at` has passed, as the token would be rejected anyway.
+create table "revoked_tokens"
+(
+    jti        uuid primary key,
+
+    expires_at timestamptz not null
+);
+
+create index on "revoked_tokens" (expires_at);
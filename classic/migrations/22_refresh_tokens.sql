@@ -0,0 +1,20 @@
+-- Long-lived tokens that can be exchanged for a new login token (JWT), see `refresh_token`.
+--
+-- Like `password_resets`, only a SHA-256 hash of the token is stored. A token is deleted when it's used, and a new one
+-- is issued in its place.
+create table "refresh_tokens"
+(
+    id         uuid primary key     default uuid_generate_v1mc(),
+
+    user_id    uuid        not null references users (id) on delete cascade,
+
+    token_hash text unique not null,
+
+    expires_at timestamptz not null,
+
+    created_at timestamptz not null default now()
+);
+
+create index on "refresh_tokens" (user_id);
+
+create index on "refresh_tokens" (expires_at);
@@ -0,0 +1,19 @@
+-- Whether the user proved they own their email, by following the link sent to it.
+alter table "users"
+    add column email_verified boolean not null default false;
+
+-- Single-use tokens for verifying a user's email, sent on signup and when the email changes.
+--
+-- Like `password_resets`, only a SHA-256 hash of the token is stored.
+create table "email_verifications"
+(
+    id         uuid primary key     default uuid_generate_v1mc(),
+
+    user_id    uuid        not null references users (id) on delete cascade,
+
+    token_hash text unique not null,
+
+    expires_at timestamptz not null,
+
+    created_at timestamptz not null default now()
+);
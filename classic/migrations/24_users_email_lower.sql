@@ -0,0 +1,8 @@
+-- Emails are now normalized to lowercase by the API, see `normalize_email`.
+--
+-- The column's `case_insensitive` collation already keeps the same address in a different case from being registered
+-- twice, so this only normalizes how existing emails are stored. The comparison has to use `ucs_basic`, or rows that
+-- only differ in case would be skipped.
+update "users"
+set email = lower(trim(email))
+where (email collate "ucs_basic") <> (lower(trim(email)) collate "ucs_basic");
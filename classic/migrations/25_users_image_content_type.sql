@@ -0,0 +1,5 @@
+-- Avatars can now be uploaded, so `image` isn't necessarily a base64 SVG from the avatar service anymore.
+--
+-- Null means SVG, which is what all avatars stored before this were.
+alter table "users"
+    add column image_content_type text;
@@ -0,0 +1,30 @@
+-- Every pair of members has exactly one ledger entry per currency, see `init_ledger_entries`.
+--
+-- Fold any duplicate entries into the earliest one first, so no outstanding balance is lost.
+with entries as (
+    select id,
+           row_number() over (partition by group_id, this_user, other_user, currency order by created_at, id) as rn,
+           sum(amount) over (partition by group_id, this_user, other_user, currency) as total
+    from "ledgers"
+)
+update "ledgers" l
+set amount = e.total
+from entries e
+where e.id = l.id
+  and e.rn = 1
+  and l.amount <> e.total;
+
+delete from "ledgers" l
+using "ledgers" keep
+where keep.group_id = l.group_id
+  and keep.this_user = l.this_user
+  and keep.other_user = l.other_user
+  and keep.currency = l.currency
+  and (keep.created_at, keep.id) < (l.created_at, l.id);
+
+alter table "ledgers"
+    add constraint ledgers_group_id_this_user_other_user_currency_key
+        unique (group_id, this_user, other_user, currency);
+
+-- Covered by the unique constraint's index now.
+drop index "ledgers_group_id_this_user_other_user_currency_idx";
@@ -0,0 +1,24 @@
+-- Friendships between users, see `http/friends.rs`.
+--
+-- A row is a request from `user_id` to `friend_id`, and the two are friends once `friend_id` accepts it. There's only
+-- ever one row per pair of users, whichever of them asked first.
+create table "friends"
+(
+    id          uuid primary key     default uuid_generate_v1mc(),
+
+    user_id     uuid        not null references users (id) on delete cascade,
+    friend_id   uuid        not null references users (id) on delete cascade,
+
+    -- Null while the request is pending.
+    accepted_at timestamptz,
+
+    created_at  timestamptz not null default now(),
+
+    check (user_id <> friend_id)
+);
+
+create unique index friends_pair_key on "friends" (least(user_id, friend_id), greatest(user_id, friend_id));
+
+create index on "friends" (user_id);
+
+create index on "friends" (friend_id);
@@ -0,0 +1,7 @@
+-- Optional two-factor authentication with TOTP codes, see `enable_2fa`.
+--
+-- `totp_secret` is encrypted, see `logic/totp.rs`. It's stored as soon as 2FA is enabled, but login only requires a
+-- code once the user confirmed they can generate valid ones, which sets `totp_enabled_at`.
+alter table "users"
+    add column totp_secret     bytea,
+    add column totp_enabled_at timestamptz;
@@ -0,0 +1,17 @@
+-- Keys clients send in an `Idempotency-Key` header when creating a transaction, so that retrying the request returns
+-- the transaction that was created the first time instead of creating another one.
+--
+-- Keys are scoped per user, and can be reused once `expires_at` has passed.
+create table "idempotency_keys"
+(
+    user_id        uuid        not null references users (id) on delete cascade,
+    key            text        not null,
+
+    transaction_id uuid        not null references transactions (id) on delete cascade,
+
+    expires_at     timestamptz not null,
+
+    created_at     timestamptz not null default now(),
+
+    primary key (user_id, key)
+);
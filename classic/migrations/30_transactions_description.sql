@@ -0,0 +1,10 @@
+-- What a transaction was for, as a first-class field rather than a key in `metadata`.
+--
+-- The API rejects longer descriptions with a proper error, see `MAX_DESCRIPTION_LENGTH`; this is only a backstop.
+alter table "transactions"
+    add column description text check (char_length(description) <= 280);
+
+-- `json` has no equality operator, so the `updated_at` trigger's `OLD is distinct from NEW` fails on any update
+-- that leaves every column before `metadata` unchanged, e.g. one that only edits the description.
+alter table "transactions"
+    alter column metadata type jsonb;
@@ -0,0 +1,4 @@
+-- Settling up is recorded as a transaction like any other, so that it updates the ledgers the same way, but flagged
+-- so that clients can show it apart from expenses, see `record_settlement`.
+alter table "transactions"
+    add column is_settlement boolean not null default false;
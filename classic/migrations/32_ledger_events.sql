@@ -0,0 +1,21 @@
+-- An append-only history of every change to a ledger entry's amount, see `update_ledger_entries`, so that it's
+-- possible to tell how a balance got to its value.
+--
+-- `caused_by_tx_id` isn't a foreign key, so that the history of a deleted transaction, and of undoing it, is kept.
+create table "ledger_events"
+(
+    id              uuid primary key     default uuid_generate_v1mc(),
+
+    group_id        uuid        not null references groups (id),
+    this_user       uuid        not null references users (id),
+    other_user      uuid        not null references users (id),
+    currency        text        not null,
+
+    -- What was added to the entry's amount.
+    delta           bigint      not null,
+    caused_by_tx_id uuid        not null,
+
+    created_at      timestamptz not null default now()
+);
+
+create index on "ledger_events" (group_id, created_at);
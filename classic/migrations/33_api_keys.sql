@@ -0,0 +1,21 @@
+-- Long-lived keys that services can authenticate with in an `X-Api-Key` header instead of logging in, see
+-- `AuthUser`.
+--
+-- Like `refresh_tokens`, only a SHA-256 hash of the key is stored. A key is valid until it's deleted.
+create table "api_keys"
+(
+    id           uuid primary key     default uuid_generate_v1mc(),
+
+    user_id      uuid        not null references users (id) on delete cascade,
+
+    -- To tell keys apart when listing them, since the key itself is only shown once.
+    name         text        not null,
+
+    key_hash     text unique not null,
+
+    last_used_at timestamptz,
+
+    created_at   timestamptz not null default now()
+);
+
+create index on "api_keys" (user_id);
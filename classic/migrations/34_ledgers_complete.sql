@@ -0,0 +1,10 @@
+-- Members who joined a group at the same time as someone else could miss their ledger entries against each other,
+-- see `ensure_ledger_complete`. Open the missing ones in the group's currency.
+insert into "ledgers" (group_id, this_user, other_user, currency)
+select this.group_id, this.user_id, other.user_id, g.currency
+from "user_groups" this
+join "user_groups" other
+    on other.group_id = this.group_id and other.user_id <> this.user_id
+join "groups" g
+    on g.id = this.group_id
+on conflict (group_id, this_user, other_user, currency) do nothing;
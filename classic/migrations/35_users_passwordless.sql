@@ -0,0 +1,4 @@
+-- Users who sign up with Google don't have a password, see `login_with_google`. They can set one later with a
+-- password reset, and until then can't log in with `POST /api/v1/users/login`.
+alter table "users"
+    alter column password_hash drop not null;
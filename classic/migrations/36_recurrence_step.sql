@@ -0,0 +1,17 @@
+-- How far apart the occurrences of a recurring transaction are, so that advancing `next_run` is spelled out once.
+create function recurrence_step(period recurrenceT) returns interval
+    language sql
+    immutable
+as
+$$
+select case period
+           when 'DAILY' then interval '1 day'
+           when 'WEEKLY' then interval '1 week'
+           else interval '1 month'
+           end
+$$;
+
+-- Like `transactions.metadata`, see `30_transactions_description.sql`: with `json`, the `updated_at` trigger fails
+-- on any update that leaves every column before `metadata` unchanged, which includes advancing `next_run`.
+alter table "recurring_transactions"
+    alter column metadata type jsonb;
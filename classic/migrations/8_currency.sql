@@ -0,0 +1,17 @@
+-- Amounts are only meaningful together with their currency (ISO 4217 code).
+--
+-- Every group has a currency that its transactions default to, and every transaction records
+-- the currency it was made in.
+alter table "groups"
+    add column currency text not null default 'MYR';
+
+alter table "transactions"
+    add column currency text;
+
+update "transactions" t
+set currency = g.currency
+from "groups" g
+where g.id = t.group_id;
+
+alter table "transactions"
+    alter column currency set not null;
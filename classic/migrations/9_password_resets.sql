@@ -0,0 +1,15 @@
+-- Single-use tokens for resetting a forgotten password.
+--
+-- Only a SHA-256 hash of the token is stored, so a leaked copy of this table can't be used to take over accounts.
+create table "password_resets"
+(
+    id         uuid primary key     default uuid_generate_v1mc(),
+
+    user_id    uuid        not null references users (id) on delete cascade,
+
+    token_hash text unique not null,
+
+    expires_at timestamptz not null,
+
+    created_at timestamptz not null default now()
+);
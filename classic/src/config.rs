@@ -18,10 +18,118 @@ pub struct Config {
     #[clap(long, env)]
     pub database_url: String,
 
+    /// The most connections to keep open to the database at once.
+    #[clap(long, env, default_value_t = 50)]
+    pub db_max_connections: u32,
+
+    /// How many connections to keep open to the database even when idle.
+    #[clap(long, env, default_value_t = 0)]
+    pub db_min_connections: u32,
+
+    /// How long, in seconds, to wait for a database connection before failing a request.
+    #[clap(long, env, default_value_t = 30)]
+    pub db_acquire_timeout_seconds: u64,
+
     /// The HMAC signing and verification key used for login tokens (JWTs).
     ///
     /// There is no required structure or format to this key as it's just fed into a hash function.
     /// In practice, it should be a long, random string that would be infeasible to brute-force.
     #[clap(long, env)]
     pub hmac_key: String,
+
+    /// The key used to encrypt users' two-factor authentication secrets in the database.
+    ///
+    /// Defaults to `hmac_key`. Set it separately so that `hmac_key` can be rotated without
+    /// locking out users with two-factor authentication enabled.
+    #[clap(long, env)]
+    pub totp_encryption_key: Option<String>,
+
+    /// How long, in seconds, a login token (JWT) stays valid after it was issued.
+    #[clap(long, env, default_value_t = 86400)]
+    pub jwt_ttl_seconds: i64,
+
+    /// How long, in seconds, a refresh token can be exchanged for a new login token after it was issued.
+    #[clap(long, env, default_value_t = 2592000)]
+    pub refresh_token_ttl_seconds: i64,
+
+    /// How long, in seconds, a group invite can be accepted after it was created.
+    #[clap(long, env, default_value_t = 604800)]
+    pub invite_ttl_seconds: i64,
+
+    /// Whether to fetch avatars for users who haven't uploaded one from the external avatar service.
+    ///
+    /// Otherwise they get a generated placeholder.
+    #[clap(long, env, default_value_t = true, action = clap::ArgAction::Set)]
+    pub avatar_fetch_enabled: bool,
+
+    /// The base URL of the avatar service.
+    #[clap(long, env, default_value = "https://joesch.moe")]
+    pub avatar_base_url: String,
+
+    /// The path of a user's avatar on the avatar service, with `{email}` standing in for their email.
+    #[clap(long, env, default_value = "/api/v1/male/{email}")]
+    pub avatar_path_template: String,
+
+    /// How long, in milliseconds, to wait on each request to the avatar service before giving up on it.
+    #[clap(long, env, default_value_t = 3000)]
+    pub avatar_fetch_timeout_ms: u64,
+
+    /// The OAuth client ids of our apps, comma-separated, that Google ID tokens may be issued to
+    /// for `POST /api/v1/users/oauth/google`.
+    ///
+    /// Logging in with Google is disabled if there are none.
+    #[clap(long, env, value_delimiter = ',')]
+    pub google_client_ids: Vec<String>,
+
+    /// Where Google publishes the keys its ID tokens are signed with.
+    #[clap(
+        long,
+        env,
+        default_value = "https://www.googleapis.com/oauth2/v3/certs"
+    )]
+    pub google_jwks_url: String,
+
+    /// Passwords shorter than this, in characters, are rejected on signup and when changing them.
+    #[clap(long, env, default_value_t = 8)]
+    pub password_min_length: usize,
+
+    /// Whether passwords have to contain at least one letter and at least one digit or symbol.
+    #[clap(long, env, default_value_t = true, action = clap::ArgAction::Set)]
+    pub password_require_complexity: bool,
+
+    /// How many failed logins for the same email lock it out for `login_lockout_seconds`.
+    #[clap(long, env, default_value_t = 5)]
+    pub login_max_failures: u32,
+
+    /// Like `login_max_failures`, but for the same IP address. Higher by default, as many users
+    /// can share an IP address.
+    #[clap(long, env, default_value_t = 20)]
+    pub login_max_failures_per_ip: u32,
+
+    /// How long, in seconds, failed logins count towards a lockout.
+    #[clap(long, env, default_value_t = 900)]
+    pub login_failure_window_seconds: u64,
+
+    /// How long, in seconds, an email or IP address stays locked out after too many failed logins.
+    #[clap(long, env, default_value_t = 900)]
+    pub login_lockout_seconds: u64,
+
+    /// Whether users have to verify their email before they can create or join groups.
+    ///
+    /// Off by default, so existing users who signed up before verification existed aren't locked out.
+    #[clap(long, env, default_value_t = false, action = clap::ArgAction::Set)]
+    pub require_email_verification: bool,
+
+    /// Whether to run the background task that posts recurring transactions when they're due.
+    #[clap(long, env, default_value_t = true, action = clap::ArgAction::Set)]
+    pub recurring_enabled: bool,
+
+    /// How often, in seconds, the background task checks for recurring transactions that are due.
+    #[clap(long, env, default_value_t = 60)]
+    pub recurring_interval_seconds: u64,
+
+    /// How long, in seconds, to wait for in-flight requests to finish after a shutdown signal
+    /// before exiting anyway.
+    #[clap(long, env, default_value_t = 30)]
+    pub shutdown_timeout_seconds: u64,
 }
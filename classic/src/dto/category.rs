@@ -0,0 +1,18 @@
+/// A wrapper type for all requests/responses for categories.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CategoryBody<T> {
+    pub category: T,
+}
+
+/// A category defined by a group for its own transactions.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Category {
+    pub id: uuid::Uuid,
+    pub group_id: uuid::Uuid,
+    pub name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewCategory {
+    pub name: String,
+}
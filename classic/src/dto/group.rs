@@ -1,3 +1,9 @@
+use super::{
+    ledger::{Balance, Ledger},
+    user::User,
+};
+use crate::http::Timestamptz;
+
 /// A wrapper type for all requests/responses from this module.
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct GroupBody<T> {
@@ -8,15 +14,101 @@ pub struct GroupBody<T> {
 pub struct Group {
     pub id: uuid::Uuid,
     pub name: String,
+    /// ISO 4217 code of the currency transactions in this group default to.
+    pub currency: String,
+    pub created_at: Timestamptz,
+}
+
+/// What a member may do in a group.
+#[derive(serde::Serialize, serde::Deserialize, sqlx::Type, Copy, Clone, PartialEq, Debug)]
+#[sqlx(type_name = "groupRoleT", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum GroupRole {
+    /// Can do anything an admin can, and also delete the group and appoint admins.
+    /// Given to whoever created the group.
+    Owner,
+    /// Can rename the group and remove other members.
+    Admin,
+    Member,
+}
+
+impl GroupRole {
+    /// Whether this role may do everything `min_role` may.
+    pub fn is_at_least(self, min_role: GroupRole) -> bool {
+        self.rank() >= min_role.rank()
+    }
+
+    fn rank(self) -> u8 {
+        match self {
+            GroupRole::Owner => 2,
+            GroupRole::Admin => 1,
+            GroupRole::Member => 0,
+        }
+    }
+}
+
+/// Users to add to a group all at once, see `groups::add_users_to_group`.
+#[derive(serde::Deserialize)]
+pub struct NewMembers {
+    pub user_ids: Vec<uuid::Uuid>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateMemberRole {
+    pub role: GroupRole,
+}
+
+/// A wrapper type for group summary responses.
+#[derive(serde::Serialize)]
+pub struct SummaryBody<T> {
+    pub summary: T,
+}
+
+/// A group together with its members and the current user's position in it.
+#[derive(serde::Serialize)]
+pub struct GroupSummary {
+    pub group: Group,
+    pub members: Vec<User>,
+    pub my_balance: Balance,
+    /// The current user's outstanding ledger entries against other members.
+    pub pairwise_balances: Vec<Ledger>,
 }
 
 #[derive(serde::Deserialize)]
 pub struct NewGroup {
     pub name: String,
+    pub currency: Option<String>,
 }
 
+/// Fields left out of the request are `None`, and fields sent as `null` are `Some(None)`.
 #[derive(serde::Deserialize, Default, PartialEq, Eq)]
 #[serde(default)]
 pub struct UpdateGroup {
-    pub name: Option<String>,
+    #[serde(deserialize_with = "present")]
+    pub name: Option<Option<String>>,
+}
+
+/// Deserializes a field that was sent, even as `null`, to `Some`, so that it can be told apart
+/// from a field that was left out.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> UpdateGroup {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn update_group_tells_null_from_missing() {
+        assert_eq!(parse(r#"{}"#).name, None);
+        assert_eq!(parse(r#"{"name":null}"#).name, Some(None));
+        assert_eq!(parse(r#"{"name":"x"}"#).name, Some(Some("x".to_string())));
+    }
 }
@@ -0,0 +1,142 @@
+use crate::{dto::money::Money, http::Timestamptz};
+
+use std::collections::HashMap;
+
+/// A wrapper type for balance responses.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BalanceBody<T> {
+    pub balance: T,
+}
+
+/// A user's net position within a group.
+///
+/// A positive `net` means the user is owed money, negative means they owe.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Balance {
+    pub group_id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    /// The group's currency, which `net` is expressed in.
+    pub currency: String,
+    /// The sum of `by_currency`, converted to `currency`.
+    pub net: Money,
+    /// The unconverted net position per currency, leaving out currencies that net to zero.
+    pub by_currency: HashMap<String, Money>,
+}
+
+/// The current user's net position within a group, along with where they stand with each of
+/// the other members.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BalanceBreakdown {
+    #[serde(flatten)]
+    pub balance: Balance,
+    /// The members the current user owes or is owed by, leaving out those they're even with.
+    pub members: Vec<CounterpartyBalance>,
+}
+
+/// Where the current user stands with one other member of a group.
+///
+/// A positive `net` means the member owes the current user, negative means the current user
+/// owes them.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CounterpartyBalance {
+    pub user_id: uuid::Uuid,
+    pub username: String,
+    /// The sum of `by_currency`, converted to the group's currency.
+    pub net: Money,
+    /// The unconverted amounts per currency, leaving out currencies that net to zero.
+    pub by_currency: HashMap<String, Money>,
+}
+
+/// A user's net position across all of their groups.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct TotalBalance {
+    pub user_id: uuid::Uuid,
+    /// The currency `total_net` is expressed in.
+    pub currency: String,
+    /// The sum of `by_currency`, converted to `currency`.
+    pub total_net: Money,
+    /// The unconverted net position per currency over all groups, leaving out currencies that
+    /// net to zero.
+    pub by_currency: HashMap<String, Money>,
+    /// The user's balance in each group they have ledger entries in, in that group's currency.
+    pub per_group: Vec<Balance>,
+}
+
+/// A wrapper type for ledger responses.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LedgerBody<T> {
+    pub ledger: T,
+}
+
+/// A directed ledger entry between two users in a group.
+///
+/// A positive `amount` means `other_user` owes `this_user`, negative means `this_user` owes.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Ledger {
+    pub this_user: uuid::Uuid,
+    pub other_user: uuid::Uuid,
+    pub currency: String,
+    pub amount: Money,
+}
+
+/// A change to the ledger entry of `this_user` against `other_user`, caused by creating,
+/// editing, reversing or deleting the transaction `caused_by_tx_id`.
+///
+/// Every change is recorded twice, once for each direction of the pair.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LedgerEvent {
+    pub id: uuid::Uuid,
+    pub this_user: uuid::Uuid,
+    pub other_user: uuid::Uuid,
+    pub currency: String,
+    /// What was added to the entry's `amount`.
+    pub delta: Money,
+    pub caused_by_tx_id: uuid::Uuid,
+    pub created_at: Timestamptz,
+}
+
+/// A wrapper type for settlement responses.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SettlementBody<T> {
+    pub settlement: T,
+}
+
+/// A suggested payment of `amount` from user `from` to user `to`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct Settlement {
+    pub from: uuid::Uuid,
+    pub to: uuid::Uuid,
+    pub amount: Money,
+}
+
+/// The net positions of all members of a group, converted to a single display currency.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct GroupBalances {
+    pub group_id: uuid::Uuid,
+    /// The currency `MemberBalance::net` is expressed in.
+    pub currency: String,
+    pub members: Vec<MemberBalance>,
+    /// Who owes whom, one entry per pair of members and currency with an outstanding amount.
+    pub debts: Vec<Debt>,
+}
+
+/// A member's net position within a group.
+///
+/// Positive amounts mean the member is owed money, negative means they owe.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct MemberBalance {
+    pub user_id: uuid::Uuid,
+    /// The sum of `by_currency`, converted to the display currency.
+    pub net: Money,
+    /// The unconverted net position per currency, leaving out currencies that net to zero.
+    pub by_currency: HashMap<String, Money>,
+}
+
+/// An outstanding amount that user `from` owes user `to`. `amount` is always positive.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Debt {
+    pub from: uuid::Uuid,
+    pub to: uuid::Uuid,
+    pub currency: String,
+    pub amount: Money,
+}
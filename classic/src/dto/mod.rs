@@ -1,3 +1,5 @@
+pub mod category;
 pub mod group;
 pub mod ledger;
+pub mod money;
 pub mod user;
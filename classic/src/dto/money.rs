@@ -0,0 +1,146 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use std::{fmt, str::FromStr};
+
+/// How many minor units make up one major unit.
+///
+/// This is fixed at two decimal places for every currency, even ones like JPY that have no minor
+/// unit in practice, so that stored amounts mean the same thing regardless of their currency.
+const MINOR_PER_MAJOR: i64 = 100;
+
+/// An amount of money as an integer number of minor units, e.g. cents.
+///
+/// In JSON this is a decimal string like `"12.50"`, so clients can't send floats that would be
+/// rounded in unexpected ways. Amounts are stored in the database as the bare number of minor
+/// units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Money(i64);
+
+impl Money {
+    pub const fn from_minor(minor: i64) -> Self {
+        Self(minor)
+    }
+
+    /// `None` if the amount doesn't fit.
+    pub fn from_major(major: i64) -> Option<Self> {
+        major.checked_mul(MINOR_PER_MAJOR).map(Self)
+    }
+
+    pub const fn minor(self) -> i64 {
+        self.0
+    }
+
+    /// The whole major units of the amount, with any minor units truncated towards zero.
+    pub const fn to_major(self) -> i64 {
+        self.0 / MINOR_PER_MAJOR
+    }
+
+    /// `None` on overflow.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let per_major = MINOR_PER_MAJOR as u64;
+
+        write!(f, "{sign}{}.{:02}", abs / per_major, abs % per_major)
+    }
+}
+
+/// Parses a decimal string with up to two decimal places, e.g. `"12"`, `"12.5"` or `"12.50"`.
+///
+/// Negative amounts are rejected, as clients give the direction of a transaction with its
+/// `tx_type` instead. They are still formatted with a leading `-`, e.g. for balances.
+impl FromStr for Money {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const INVALID: &str = "must be a decimal with at most 2 decimal places";
+
+        if s.starts_with('-') {
+            return Err("must not be negative");
+        }
+        let (major, minor) = s.split_once('.').unwrap_or((s, ""));
+
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if major.is_empty() || !all_digits(major) || !all_digits(minor) || minor.len() > 2 {
+            return Err(INVALID);
+        }
+
+        let major: i64 = major.parse().map_err(|_| "is too large")?;
+        // `"12.5"` means 50 minor units, not 5.
+        let minor: i64 = format!("{minor:0<2}").parse().map_err(|_| INVALID)?;
+
+        major
+            .checked_mul(MINOR_PER_MAJOR)
+            .and_then(|major| major.checked_add(minor))
+            .map(Self)
+            .ok_or("is too large")
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+
+        s.parse()
+            .map_err(|e| serde::de::Error::custom(format!("invalid amount {s:?}: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimals() {
+        for (s, minor) in [
+            ("0", 0),
+            ("12", 1200),
+            ("12.5", 1250),
+            ("12.50", 1250),
+            ("0.05", 5),
+            ("92233720368547758.07", i64::MAX),
+        ] {
+            assert_eq!(s.parse(), Ok(Money::from_minor(minor)), "{s}");
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for minor in [0, 5, 50, 1250, 100_000, i64::MAX] {
+            let money = Money::from_minor(minor);
+            assert_eq!(money.to_string().parse(), Ok(money));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        for s in [
+            "", ".5", "1.234", "1e3", " 1", "1,00", "+1", "0x10", "-0.5", "-1",
+        ] {
+            assert!(s.parse::<Money>().is_err(), "{s:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        for s in [
+            "92233720368547758.08",
+            "92233720368547759",
+            "99999999999999999999",
+        ] {
+            assert_eq!(s.parse::<Money>(), Err("is too large"), "{s}");
+        }
+    }
+}
@@ -1,6 +1,18 @@
+use super::group::GroupRole;
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct User {
     pub id: uuid::Uuid,
     pub email: String,
     pub username: String,
+    /// The user's role in the group they were listed for.
+    pub role: GroupRole,
+}
+
+/// What other users can see of a user they share a group with.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct UserProfile {
+    pub id: uuid::Uuid,
+    pub username: String,
+    pub email: String,
 }
@@ -0,0 +1,163 @@
+use super::{
+    extractor::AuthToken,
+    types::{Created, Timestamptz},
+    users::{generate_token, hash_token},
+};
+use crate::{
+    commons::{to_sqlx_uuid, to_uuid},
+    http::{error::Error, ApiContext, Result},
+};
+
+use axum::{
+    extract::{Extension, Path},
+    routing::{delete, post},
+    Json, Router,
+};
+
+/// Key names longer than this, in characters, are rejected.
+const MAX_NAME_LENGTH: usize = 100;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/v1/me/api-keys", post(create_api_key).get(list_api_keys))
+        .route("/v1/me/api-keys/:key_id", delete(delete_api_key))
+}
+
+/// A wrapper type for all requests/responses from this module.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ApiKeyBody<T> {
+    api_key: T,
+}
+
+#[derive(serde::Deserialize)]
+struct NewApiKey {
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+struct ApiKey {
+    id: uuid::Uuid,
+    name: String,
+    last_used_at: Option<Timestamptz>,
+    created_at: Timestamptz,
+}
+
+/// A newly created key, the only time the key itself is returned.
+#[derive(serde::Serialize)]
+struct CreatedApiKey {
+    #[serde(flatten)]
+    api_key: ApiKey,
+    /// Send this in the `X-Api-Key` header to authenticate as the user who created it.
+    key: String,
+}
+
+/// Create an API key for services to authenticate as the current user with, see `AuthUser`.
+///
+/// A key can do anything the user can, so it should be kept as secret as a password.
+async fn create_api_key(
+    ctx: Extension<ApiContext>,
+    auth_token: AuthToken,
+    Json(req): Json<ApiKeyBody<NewApiKey>>,
+) -> Result<Created<ApiKeyBody<CreatedApiKey>>> {
+    let auth_user = auth_token.user;
+
+    let name = req.api_key.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(Error::unprocessable_entity([(
+            "name",
+            format!("must be between 1 and {MAX_NAME_LENGTH} characters"),
+        )]));
+    }
+
+    let key = generate_token();
+
+    let row = sqlx::query!(
+        r#"
+            insert into "api_keys" (user_id, name, key_hash)
+            values ($1, $2, $3)
+            returning id, created_at
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+        name,
+        hash_token(&key),
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    log::info!(
+        "[create_api_key] user {} created api key {}",
+        auth_user.user_id,
+        row.id
+    );
+
+    Ok(Created::new(ApiKeyBody {
+        api_key: CreatedApiKey {
+            api_key: ApiKey {
+                id: to_uuid(row.id),
+                name: name.to_string(),
+                last_used_at: None,
+                created_at: row.created_at.into(),
+            },
+            key,
+        },
+    }))
+}
+
+/// List the current user's API keys, newest first, without the keys themselves.
+async fn list_api_keys(
+    ctx: Extension<ApiContext>,
+    auth_token: AuthToken,
+) -> Result<Json<ApiKeyBody<Vec<ApiKey>>>> {
+    let auth_user = auth_token.user;
+
+    let api_key = sqlx::query!(
+        r#"
+            select id, name, last_used_at, created_at
+            from "api_keys"
+            where user_id = $1
+            order by created_at desc, id desc
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|k| ApiKey {
+        id: to_uuid(k.id),
+        name: k.name,
+        last_used_at: k.last_used_at.map(Into::into),
+        created_at: k.created_at.into(),
+    })
+    .collect();
+
+    Ok(Json(ApiKeyBody { api_key }))
+}
+
+/// Revoke one of the current user's API keys.
+async fn delete_api_key(
+    ctx: Extension<ApiContext>,
+    auth_token: AuthToken,
+    Path(key_id): Path<uuid::Uuid>,
+) -> Result<()> {
+    let auth_user = auth_token.user;
+
+    let deleted = sqlx::query!(
+        r#"delete from "api_keys" where id = $1 and user_id = $2"#,
+        to_sqlx_uuid(key_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .execute(&ctx.db)
+    .await?
+    .rows_affected();
+
+    if deleted == 0 {
+        return Err(Error::NotFound);
+    }
+
+    log::info!(
+        "[delete_api_key] user {} revoked api key {key_id}",
+        auth_user.user_id
+    );
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use super::{extractor::AuthUser, types::Created, users::is_user_in_group};
+use crate::{
+    commons::{to_sqlx_uuid, to_uuid},
+    dto::category::{Category, CategoryBody, NewCategory},
+    http::{
+        error::{Error, ResultExt},
+        ApiContext, Result,
+    },
+};
+
+use axum::{
+    extract::{Extension, Path},
+    routing::{get, put},
+    Json, Router,
+};
+
+/// What a client filters on to find transactions without a category, so no category may be
+/// named this.
+pub(super) const UNCATEGORIZED: &str = "uncategorized";
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/v1/groups/:group_id/categories",
+            get(list_categories).post(create_category),
+        )
+        .route(
+            "/v1/groups/:group_id/categories/:category_id",
+            put(update_category).delete(delete_category),
+        )
+}
+
+async fn create_category(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+    Json(req): Json<CategoryBody<NewCategory>>,
+) -> Result<Created<CategoryBody<Category>>> {
+    ensure_member(&ctx, auth_user, group_id).await?;
+    let name = validate_name(&req.category.name)?;
+
+    let category_id = sqlx::query_scalar!(
+        r#"INSERT INTO "categories" (group_id, name) VALUES ($1, $2) RETURNING id"#,
+        to_sqlx_uuid(group_id),
+        name,
+    )
+    .fetch_one(&ctx.db)
+    .await
+    .on_constraint("categories_group_id_name_key", |_| {
+        Error::unprocessable_entity([("name", "category name taken")])
+    })?;
+
+    Ok(Created::new(CategoryBody {
+        category: Category {
+            id: to_uuid(category_id),
+            group_id,
+            name,
+        },
+    }))
+}
+
+async fn list_categories(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<CategoryBody<Vec<Category>>>> {
+    ensure_member(&ctx, auth_user, group_id).await?;
+
+    let category = sqlx::query!(
+        r#"SELECT id, name FROM "categories" WHERE group_id = $1 ORDER BY name"#,
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|c| Category {
+        id: to_uuid(c.id),
+        group_id,
+        name: c.name,
+    })
+    .collect();
+
+    Ok(Json(CategoryBody { category }))
+}
+
+async fn update_category(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path((group_id, category_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Json(req): Json<CategoryBody<NewCategory>>,
+) -> Result<Json<CategoryBody<Category>>> {
+    ensure_member(&ctx, auth_user, group_id).await?;
+    let name = validate_name(&req.category.name)?;
+
+    sqlx::query!(
+        r#"UPDATE "categories" SET name = $1 WHERE id = $2 AND group_id = $3 RETURNING id"#,
+        name,
+        to_sqlx_uuid(category_id),
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await
+    .on_constraint("categories_group_id_name_key", |_| {
+        Error::unprocessable_entity([("name", "category name taken")])
+    })?
+    .ok_or(Error::NotFound)?;
+
+    Ok(Json(CategoryBody {
+        category: Category {
+            id: category_id,
+            group_id,
+            name,
+        },
+    }))
+}
+
+/// Delete a category. Transactions in it become uncategorized.
+async fn delete_category(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path((group_id, category_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<()> {
+    ensure_member(&ctx, auth_user, group_id).await?;
+
+    let result = sqlx::query!(
+        r#"DELETE FROM "categories" WHERE id = $1 AND group_id = $2"#,
+        to_sqlx_uuid(category_id),
+        to_sqlx_uuid(group_id),
+    )
+    .execute(&ctx.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+async fn ensure_member(ctx: &ApiContext, auth_user: AuthUser, group_id: uuid::Uuid) -> Result<()> {
+    if !is_user_in_group(
+        Extension(ctx.clone()),
+        Path(auth_user.user_id),
+        Path(group_id),
+    )
+    .await?
+    .0
+    {
+        return Err(Error::Forbidden);
+    }
+
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::unprocessable_entity([("name", "must not be empty")]));
+    }
+    if name.eq_ignore_ascii_case(UNCATEGORIZED) {
+        return Err(Error::unprocessable_entity([("name", "is reserved")]));
+    }
+
+    Ok(name.to_string())
+}
@@ -1,5 +1,8 @@
 use axum::{
-    http::{header::WWW_AUTHENTICATE, HeaderMap, HeaderValue, StatusCode},
+    http::{
+        header::{RETRY_AFTER, WWW_AUTHENTICATE},
+        HeaderMap, HeaderValue, StatusCode,
+    },
     response::{IntoResponse, Response},
     Json,
 };
@@ -7,20 +10,47 @@ use sqlx::error::DatabaseError;
 
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::time::Duration;
 
 /// A common error type that can be used throughout the API.
 ///
 /// Can be returned in a `Result` from an API handler function.
 ///
 /// For convenience, this represents both API errors as well as internal recoverable errors,
-/// and maps them to appropriate status codes along with at least a minimally useful error
-/// message in a plain text body, or a JSON body in the case of `UnprocessableEntity`.
+/// and maps them to appropriate status codes along with a JSON body of the form:
+///
+/// ```json
+/// { "error": { "code": "UNPROCESSABLE_ENTITY", "message": "...", "fields": { "name": ["..."] } } }
+/// ```
+///
+/// `code` is stable and meant for clients to branch on, see `Error::code()` for the possible
+/// values. `fields` is only present for `UnprocessableEntity`.
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     /// Return `401 Unauthorized`
     #[error("authentication required")]
     Unauthorized,
 
+    /// Return `401 Unauthorized` when a route requiring authentication is called without an
+    /// `Authorization` header.
+    #[error("authentication required")]
+    NoToken,
+
+    /// Return `401 Unauthorized` for a token that is malformed, isn't signed by us, or was
+    /// revoked, e.g. by logging out.
+    #[error("invalid token")]
+    InvalidToken,
+
+    /// Return `401 Unauthorized` for a token that is otherwise valid but past its expiry, so the
+    /// client knows to get a new one with its refresh token rather than logging in again.
+    #[error("token expired")]
+    TokenExpired,
+
+    /// Return `401 Unauthorized` when logging in to an account with two-factor authentication
+    /// enabled without a code, so the client knows to ask for one.
+    #[error("two-factor authentication code required")]
+    TotpRequired,
+
     /// Return `403 Forbidden`
     #[error("user may not perform that action")]
     Forbidden,
@@ -29,6 +59,10 @@ pub enum Error {
     #[error("resource not found")]
     NotFound,
 
+    /// Return `429 Too Many Requests` with a `Retry-After` header, e.g. after too many failed logins.
+    #[error("too many attempts, try again later")]
+    TooManyRequests { retry_after: Duration },
+
     /// Return `422 Unprocessable Entity`
     ///
     /// For a good API, the other status codes should also ideally map to some sort of JSON body
@@ -97,12 +131,46 @@ impl Error {
         Self::UnprocessableEntity { errors: error_map }
     }
 
+    /// The machine-readable `code` in the response body:
+    ///
+    /// * `UNAUTHORIZED` for `Unauthorized`
+    /// * `NO_TOKEN` for `NoToken`
+    /// * `INVALID_TOKEN` for `InvalidToken`
+    /// * `TOKEN_EXPIRED` for `TokenExpired`
+    /// * `TOTP_REQUIRED` for `TotpRequired`
+    /// * `FORBIDDEN` for `Forbidden`
+    /// * `NOT_FOUND` for `NotFound`
+    /// * `UNPROCESSABLE_ENTITY` for `UnprocessableEntity`
+    /// * `TOO_MANY_REQUESTS` for `TooManyRequests`
+    /// * `INTERNAL` for `Sqlx` and `Anyhow`
+    ///
+    /// Clients depend on these, so don't change existing ones.
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Unauthorized => "UNAUTHORIZED",
+            Self::NoToken => "NO_TOKEN",
+            Self::InvalidToken => "INVALID_TOKEN",
+            Self::TokenExpired => "TOKEN_EXPIRED",
+            Self::TotpRequired => "TOTP_REQUIRED",
+            Self::Forbidden => "FORBIDDEN",
+            Self::NotFound => "NOT_FOUND",
+            Self::UnprocessableEntity { .. } => "UNPROCESSABLE_ENTITY",
+            Self::TooManyRequests { .. } => "TOO_MANY_REQUESTS",
+            Self::Sqlx(_) | Self::Anyhow(_) => "INTERNAL",
+        }
+    }
+
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Unauthorized
+            | Self::NoToken
+            | Self::InvalidToken
+            | Self::TokenExpired
+            | Self::TotpRequired => StatusCode::UNAUTHORIZED,
             Self::Forbidden => StatusCode::FORBIDDEN,
             Self::NotFound => StatusCode::NOT_FOUND,
             Self::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             Self::Sqlx(_) | Self::Anyhow(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -111,20 +179,24 @@ impl Error {
 /// Axum allows you to return `Result` from handler functions, but the error type
 /// also must be some sort of response type.
 ///
-/// By default, the generated `Display` impl is used to return a plaintext error message
-/// to the client.
+/// The generated `Display` impl is used as the `message` in the JSON body.
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        match self {
-            Self::UnprocessableEntity { errors } => {
-                #[derive(serde::Serialize)]
-                struct Errors {
-                    errors: HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>,
-                }
+        #[derive(serde::Serialize)]
+        struct ErrorBody {
+            error: ErrorDetails,
+        }
 
-                return (StatusCode::UNPROCESSABLE_ENTITY, Json(Errors { errors })).into_response();
-            }
-            Self::Unauthorized => {
+        #[derive(serde::Serialize)]
+        struct ErrorDetails {
+            code: &'static str,
+            message: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            fields: Option<HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>>,
+        }
+
+        match self {
+            Self::Unauthorized | Self::NoToken | Self::InvalidToken | Self::TokenExpired => {
                 return (
                     self.status_code(),
                     // Include the `WWW-Authenticate` challenge required in the specification
@@ -136,20 +208,22 @@ impl IntoResponse for Error {
                     [(WWW_AUTHENTICATE, HeaderValue::from_static("Token"))]
                         .into_iter()
                         .collect::<HeaderMap>(),
-                    self.to_string(),
+                    Json(ErrorBody {
+                        error: ErrorDetails {
+                            code: self.code(),
+                            message: self.to_string(),
+                            fields: None,
+                        },
+                    }),
                 )
                     .into_response();
             }
 
             Self::Sqlx(ref e) => {
-                // TODO: we probably want to use `tracing` instead
-                // so that this gets linked to the HTTP request by `TraceLayer`.
                 log::error!("SQLx error: {:?}", e);
             }
 
             Self::Anyhow(ref e) => {
-                // TODO: we probably want to use `tracing` instead
-                // so that this gets linked to the HTTP request by `TraceLayer`.
                 log::error!("Generic error: {:?}", e);
             }
 
@@ -157,7 +231,32 @@ impl IntoResponse for Error {
             _ => (),
         }
 
-        (self.status_code(), self.to_string()).into_response()
+        let status = self.status_code();
+        let code = self.code();
+        let message = self.to_string();
+        let mut headers = HeaderMap::new();
+        if let Self::TooManyRequests { retry_after } = &self {
+            // Rounded up, so that a client retrying right on time isn't turned away again.
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            headers.insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        let fields = match self {
+            Self::UnprocessableEntity { errors } => Some(errors),
+            _ => None,
+        };
+
+        (
+            status,
+            headers,
+            Json(ErrorBody {
+                error: ErrorDetails {
+                    code,
+                    message,
+                    fields,
+                },
+            }),
+        )
+            .into_response()
     }
 }
 
@@ -211,3 +310,74 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Turn `error` into a response and decode its status and JSON body.
+    async fn respond(error: Error) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn forbidden_has_code_and_message() {
+        let (status, body) = respond(Error::Forbidden).await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": {
+                    "code": "FORBIDDEN",
+                    "message": "user may not perform that action",
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn not_found_has_code_and_message() {
+        let (status, body) = respond(Error::NotFound).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": {
+                    "code": "NOT_FOUND",
+                    "message": "resource not found",
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn unprocessable_entity_lists_fields() {
+        let error = Error::unprocessable_entity([
+            ("amount", "must be positive"),
+            ("amount", "must have at most 2 decimal places"),
+            ("currency", "is not supported"),
+        ]);
+        let (status, body) = respond(error).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": {
+                    "code": "UNPROCESSABLE_ENTITY",
+                    "message": "error in the request body",
+                    "fields": {
+                        "amount": ["must be positive", "must have at most 2 decimal places"],
+                        "currency": ["is not supported"],
+                    },
+                }
+            })
+        );
+    }
+}
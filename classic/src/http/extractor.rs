@@ -1,4 +1,7 @@
-use crate::http::{error::Error, ApiContext};
+use crate::{
+    commons::{to_sqlx_uuid, to_uuid},
+    http::{error::Error, users::hash_token, ApiContext},
+};
 
 use async_trait::async_trait;
 use axum::{
@@ -11,22 +14,53 @@ use jwt::{SignWithKey, VerifyWithKey};
 use sha2::Sha384;
 use time::OffsetDateTime;
 
-const DEFAULT_SESSION_LENGTH: time::Duration = time::Duration::weeks(2);
-
 const SCHEME_PREFIX: &str = "Bearer ";
 
+/// The header services send an API key in, see `api_keys::create_api_key`.
+const API_KEY_HEADER: &str = "x-api-key";
+
 /// Add this as a parameter to a handler function to require the user to be logged in.
 ///
-/// Parses a JWT from the `Authorization: Token <token>` header.
+/// Parses a JWT from the `Authorization: Bearer <token>` header, rejecting the request with
+/// `Error::NoToken`, `Error::InvalidToken` or `Error::TokenExpired` so that clients can tell
+/// whether to log in, or to refresh their token.
+///
+/// Without an `Authorization` header, an API key in the `X-Api-Key` header is accepted instead.
 #[derive(Debug, Clone, Copy)]
 pub struct AuthUser {
     pub user_id: uuid::Uuid,
 }
 
+/// Like `AuthUser`, but also identifies the token the user is logged in with,
+/// so that it can be revoked.
+///
+/// Only login tokens are accepted, not API keys, so use this instead of `AuthUser` on routes
+/// that an API key shouldn't be enough for, like managing API keys, two-factor authentication
+/// or credentials.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthToken {
+    pub user: AuthUser,
+    /// The token's unique id, from the `jti` claim.
+    pub jti: uuid::Uuid,
+    /// When the token expires, as a Unix timestamp.
+    pub exp: i64,
+}
+
+/// Add this as a parameter to a handler function to accept an optional `Idempotency-Key` header,
+/// which clients send to make retrying a request safe.
+///
+/// The key is opaque to us, but has to be between 1 and `IdempotencyKey::MAX_LENGTH` characters.
+#[derive(Debug, Clone, Default)]
+pub struct IdempotencyKey(pub Option<String>);
+
+impl IdempotencyKey {
+    pub const MAX_LENGTH: usize = 255;
+}
+
 /// Add this as a parameter to a handler function to optionally check if the user is logged in.
 ///
-/// If the `Authorization` header is absent then this will be `Self(None)`, otherwise it will
-/// validate the token.
+/// If neither the `Authorization` nor the `X-Api-Key` header is present then this will be
+/// `Self(None)`, otherwise it will validate the token or key, see `AuthUser`.
 ///
 /// This is in contrast to directly using `Option<AuthUser>`, which will be `None` if there
 /// is *any* error in deserializing, which isn't exactly what we want.
@@ -35,54 +69,111 @@ pub struct MaybeAuthUser(pub Option<AuthUser>);
 #[derive(serde::Serialize, serde::Deserialize)]
 struct AuthUserClaims {
     user_id: uuid::Uuid,
+    /// The user's `token_version` when this token was issued.
+    token_version: i32,
+    /// Standard JWT `jti` claim, used to revoke individual tokens.
+    jti: uuid::Uuid,
+    /// Standard JWT `iat` claim.
+    iat: i64,
     /// Standard JWT `exp` claim.
     exp: i64,
 }
 
 impl AuthUser {
-    pub(in crate::http) fn to_jwt(self, ctx: &ApiContext) -> String {
-        let hmac = Hmac::<Sha384>::new_from_slice(ctx.config.hmac_key.as_bytes())
+    /// `token_version` must be the user's current `users.token_version`, otherwise
+    /// the token is rejected as soon as it's used.
+    pub(in crate::http) fn to_jwt(self, ctx: &ApiContext, token_version: i32) -> String {
+        self.to_jwt_with_ttl(
+            &ctx.config.hmac_key,
+            token_version,
+            ctx.config.jwt_ttl_seconds,
+        )
+    }
+
+    /// Like `to_jwt`, but with the key and the number of seconds the token stays valid for
+    /// given explicitly.
+    fn to_jwt_with_ttl(self, hmac_key: &str, token_version: i32, ttl_seconds: i64) -> String {
+        let hmac = Hmac::<Sha384>::new_from_slice(hmac_key.as_bytes())
             .expect("HMAC-SHA-384 can accept any key length");
 
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
         AuthUserClaims {
             user_id: self.user_id,
-            exp: (OffsetDateTime::now_utc() + DEFAULT_SESSION_LENGTH).unix_timestamp(),
+            token_version,
+            jti: uuid::Uuid::new_v4(),
+            iat: now,
+            exp: now + ttl_seconds,
         }
         .sign_with_key(&hmac)
         .expect("HMAC signing should be infallible")
     }
 
     /// Attempt to parse `Self` from an `Authorization` header.
-    fn from_authorization(ctx: &ApiContext, auth_header: &HeaderValue) -> Result<Self, Error> {
-        let auth_header = auth_header.to_str().map_err(|_| {
-            log::debug!("Authorization header is not UTF-8");
-            Error::Unauthorized
+    async fn from_authorization(
+        ctx: &ApiContext,
+        auth_header: &HeaderValue,
+    ) -> Result<Self, Error> {
+        AuthToken::from_authorization(ctx, auth_header)
+            .await
+            .map(|token| token.user)
+    }
+
+    /// Attempt to parse `Self` from an `X-Api-Key` header.
+    async fn from_api_key(ctx: &ApiContext, api_key: &HeaderValue) -> Result<Self, Error> {
+        let api_key = api_key.to_str().map_err(|_| {
+            log::debug!("X-Api-Key header is not UTF-8");
+            Error::InvalidToken
         })?;
 
-        if !auth_header.starts_with(SCHEME_PREFIX) {
-            log::debug!(
-                "Authorization header is using the wrong scheme: {:?}",
-                auth_header
-            );
-            return Err(Error::Unauthorized);
+        let user_id = sqlx::query_scalar!(
+            r#"
+                update "api_keys"
+                set last_used_at = now()
+                where key_hash = $1
+                returning user_id
+            "#,
+            hash_token(api_key),
+        )
+        .fetch_optional(&ctx.db)
+        .await?
+        .ok_or_else(|| {
+            log::debug!("unknown API key");
+            Error::InvalidToken
+        })?;
+
+        Ok(Self {
+            user_id: to_uuid(user_id),
+        })
+    }
+
+    /// Authenticate with the `Authorization` header, or else the `X-Api-Key` header.
+    async fn from_headers(ctx: &ApiContext, parts: &Parts) -> Result<Option<Self>, Error> {
+        if let Some(auth_header) = parts.headers.get(AUTHORIZATION) {
+            return Self::from_authorization(ctx, auth_header).await.map(Some);
+        }
+        if let Some(api_key) = parts.headers.get(API_KEY_HEADER) {
+            return Self::from_api_key(ctx, api_key).await.map(Some);
         }
 
-        let token = &auth_header[SCHEME_PREFIX.len()..];
+        Ok(None)
+    }
+}
 
-        let jwt =
-            jwt::Token::<jwt::Header, AuthUserClaims, _>::parse_unverified(token).map_err(|e| {
-                log::debug!(
-                    "failed to parse Authorization header {:?}: {}",
-                    auth_header,
-                    e
-                );
-                Error::Unauthorized
-            })?;
+impl AuthUserClaims {
+    /// Parse and verify a JWT signed with `hmac_key`, checking that it hasn't expired.
+    ///
+    /// Whether it has been revoked since is up to the caller, see `AuthToken::from_authorization`.
+    fn from_jwt(hmac_key: &str, token: &str) -> Result<Self, Error> {
+        let jwt = jwt::Token::<jwt::Header, Self, _>::parse_unverified(token).map_err(|e| {
+            log::debug!("failed to parse JWT {:?}: {}", token, e);
+            Error::InvalidToken
+        })?;
 
         // Realworld doesn't specify the signing algorithm for use with the JWT tokens
         // so we picked SHA-384 (HS-384) as the HMAC, as it is more difficult to brute-force
         // than SHA-256 (recommended by the JWT spec) at the cost of a slightly larger token.
-        let hmac = Hmac::<Sha384>::new_from_slice(ctx.config.hmac_key.as_bytes())
+        let hmac = Hmac::<Sha384>::new_from_slice(hmac_key.as_bytes())
             .expect("HMAC-SHA-384 can accept any key length");
 
         // When choosing a JWT implementation, be sure to check that it validates that the signing
@@ -90,14 +181,47 @@ impl AuthUser {
         // The `jwt` crate does.
         let jwt = jwt.verify_with_key(&hmac).map_err(|e| {
             log::debug!("JWT failed to verify: {}", e);
-            Error::Unauthorized
+            Error::InvalidToken
         })?;
 
         let (_header, claims) = jwt.into();
 
-        // Because JWTs are stateless, we don't really have any mechanism here to invalidate them
-        // besides expiration. You probably want to add more checks, like ensuring the user ID
-        // exists and has not been deleted/banned/deactivated.
+        if claims.exp < OffsetDateTime::now_utc().unix_timestamp() {
+            log::debug!("token expired");
+            return Err(Error::TokenExpired);
+        }
+
+        Ok(claims)
+    }
+}
+
+impl AuthToken {
+    /// Attempt to parse `Self` from an `Authorization` header.
+    async fn from_authorization(
+        ctx: &ApiContext,
+        auth_header: &HeaderValue,
+    ) -> Result<Self, Error> {
+        let auth_header = auth_header.to_str().map_err(|_| {
+            log::debug!("Authorization header is not UTF-8");
+            Error::InvalidToken
+        })?;
+
+        if !auth_header.starts_with(SCHEME_PREFIX) {
+            log::debug!(
+                "Authorization header is using the wrong scheme: {:?}",
+                auth_header
+            );
+            return Err(Error::InvalidToken);
+        }
+
+        let token = &auth_header[SCHEME_PREFIX.len()..];
+
+        let claims = AuthUserClaims::from_jwt(&ctx.config.hmac_key, token)?;
+
+        // Because JWTs are stateless, the only mechanisms we have here to invalidate them
+        // besides expiration are checked against the database below: bumping
+        // `users.token_version` (see `logout_all`) invalidates every token issued before, and
+        // a token's `jti` ending up in `revoked_tokens` (see `logout`) invalidates just that one.
         //
         // You could also use the user's password hash as part of the keying material for the HMAC,
         // so changing their password invalidates their existing sessions.
@@ -119,13 +243,33 @@ impl AuthUser {
         // This also has the benefit of avoiding having to deal with securely storing the session
         // token on the frontend.
 
-        if claims.exp < OffsetDateTime::now_utc().unix_timestamp() {
-            log::debug!("token expired");
-            return Err(Error::Unauthorized);
+        // This also rejects tokens of users that no longer exist.
+        let user = sqlx::query!(
+            r#"
+                select
+                    token_version,
+                    exists(select 1 from "revoked_tokens" where jti = $2) as "revoked!"
+                from "users"
+                where id = $1
+            "#,
+            to_sqlx_uuid(claims.user_id),
+            to_sqlx_uuid(claims.jti),
+        )
+        .fetch_optional(&ctx.db)
+        .await?
+        .ok_or(Error::InvalidToken)?;
+
+        if claims.token_version != user.token_version || user.revoked {
+            log::debug!("token revoked");
+            return Err(Error::InvalidToken);
         }
 
         Ok(Self {
-            user_id: claims.user_id,
+            user: AuthUser {
+                user_id: claims.user_id,
+            },
+            jti: claims.jti,
+            exp: claims.exp,
         })
     }
 }
@@ -153,10 +297,22 @@ impl FromRequestParts<()> for AuthUser {
             .await
             .expect("BUG: ApiContext was not added as an extension");
 
-        // Get the value of the `Authorization` header, if it was sent at all.
-        let auth_header = req.headers.get(AUTHORIZATION).ok_or(Error::Unauthorized)?;
+        Self::from_headers(&ctx, req).await?.ok_or(Error::NoToken)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<()> for AuthToken {
+    type Rejection = Error;
+
+    async fn from_request_parts(req: &mut Parts, s: &()) -> Result<Self, Self::Rejection> {
+        let ctx: Extension<ApiContext> = Extension::from_request_parts(req, s)
+            .await
+            .expect("BUG: ApiContext was not added as an extension");
+
+        let auth_header = req.headers.get(AUTHORIZATION).ok_or(Error::NoToken)?;
 
-        Self::from_authorization(&ctx, auth_header)
+        Self::from_authorization(&ctx, auth_header).await
     }
 }
 
@@ -169,12 +325,117 @@ impl FromRequestParts<()> for MaybeAuthUser {
             .await
             .expect("BUG: ApiContext was not added as an extension");
 
-        // Get the value of the `Authorization` header, if it was sent at all.
+        AuthUser::from_headers(&ctx, req).await.map(Self)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<()> for IdempotencyKey {
+    type Rejection = Error;
 
-        AuthUser::from_authorization(
-            &ctx,
-            req.headers.get(AUTHORIZATION).ok_or(Error::Unauthorized)?,
-        )
-        .map(|au| Self(Some(au)))
+    async fn from_request_parts(req: &mut Parts, _: &()) -> Result<Self, Self::Rejection> {
+        let Some(key) = req.headers.get("idempotency-key") else {
+            return Ok(Self(None));
+        };
+
+        let key = key.to_str().map_err(|_| {
+            Error::unprocessable_entity([("idempotency_key", "must be visible ASCII")])
+        })?;
+        if key.is_empty() || key.len() > Self::MAX_LENGTH {
+            return Err(Error::unprocessable_entity([(
+                "idempotency_key",
+                "must be between 1 and 255 characters",
+            )]));
+        }
+
+        Ok(Self(Some(key.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Pool, Postgres};
+
+    const HMAC_KEY: &str = "test-hmac-key";
+
+    fn user() -> AuthUser {
+        AuthUser {
+            user_id: uuid::Uuid::new_v4(),
+        }
+    }
+
+    /// The parts of a request with the given `Authorization` header, if any.
+    fn parts(db: Pool<Postgres>, authorization: Option<&str>) -> Parts {
+        let mut req = http::Request::builder().extension(ApiContext::for_tests(db));
+        if let Some(authorization) = authorization {
+            req = req.header(AUTHORIZATION, authorization);
+        }
+
+        req.body(()).unwrap().into_parts().0
+    }
+
+    #[sqlx::test]
+    async fn missing_header_is_no_token(db: Pool<Postgres>) {
+        assert!(matches!(
+            AuthUser::from_request_parts(&mut parts(db.clone(), None), &()).await,
+            Err(Error::NoToken)
+        ));
+        assert!(matches!(
+            AuthToken::from_request_parts(&mut parts(db, None), &()).await,
+            Err(Error::NoToken)
+        ));
+    }
+
+    #[sqlx::test]
+    async fn malformed_header_is_invalid_token(db: Pool<Postgres>) {
+        let token = user().to_jwt_with_ttl(HMAC_KEY, 0, 60);
+
+        for authorization in [
+            format!("Token {token}"),
+            token,
+            "Bearer ".to_string(),
+            "Bearer not-a-jwt".to_string(),
+        ] {
+            assert!(
+                matches!(
+                    AuthUser::from_request_parts(&mut parts(db.clone(), Some(&authorization)), &())
+                        .await,
+                    Err(Error::InvalidToken)
+                ),
+                "{authorization:?} was not rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn fresh_token_is_accepted() {
+        let user = user();
+        let token = user.to_jwt_with_ttl(HMAC_KEY, 3, 60);
+
+        let claims = AuthUserClaims::from_jwt(HMAC_KEY, &token).unwrap();
+        assert_eq!(claims.user_id, user.user_id);
+        assert_eq!(claims.token_version, 3);
+        assert_eq!(claims.exp - claims.iat, 60);
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let token = user().to_jwt_with_ttl(HMAC_KEY, 0, -1);
+
+        assert!(matches!(
+            AuthUserClaims::from_jwt(HMAC_KEY, &token),
+            Err(Error::TokenExpired)
+        ));
+    }
+
+    #[test]
+    fn token_signed_with_another_key_is_rejected() {
+        let token = user().to_jwt_with_ttl("another-key", 0, 60);
+
+        assert!(matches!(
+            AuthUserClaims::from_jwt(HMAC_KEY, &token),
+            Err(Error::InvalidToken)
+        ));
     }
 }
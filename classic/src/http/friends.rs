@@ -0,0 +1,266 @@
+use super::{extractor::AuthUser, types::Created};
+use crate::{
+    commons::{to_sqlx_uuid, to_uuid},
+    http::{
+        error::{Error, ResultExt},
+        ApiContext, Pagination, Result,
+    },
+};
+
+use axum::{
+    extract::{Extension, Path},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/v1/me/friends", get(list_friends))
+        .route("/v1/me/friends/requests", get(list_friend_requests))
+        .route(
+            "/v1/me/friends/:user_id",
+            post(add_friend).delete(remove_friend),
+        )
+}
+
+/// A wrapper type for all requests/responses from this module.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FriendBody<T> {
+    friend: T,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, PartialEq)]
+enum FriendStatus {
+    /// Waiting for the other user to accept.
+    Pending,
+    Accepted,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Friend {
+    id: uuid::Uuid,
+    /// Left out of a request the current user sent until it's accepted, so that asking anyone
+    /// to be friends doesn't reveal who they are.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    /// Left out along with `username`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    status: FriendStatus,
+}
+
+/// Ask `user_id` to be friends, or accept their request if they already asked.
+///
+/// Responds with `201 Created` for a new request, and `200 OK` once the two are friends. The
+/// user's username and email are only included in the latter.
+async fn add_friend(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(user_id): Path<uuid::Uuid>,
+) -> Result<Response> {
+    if user_id == auth_user.user_id {
+        return Err(Error::unprocessable_entity([(
+            "user_id",
+            "cannot befriend yourself",
+        )]));
+    }
+
+    let mut tx = ctx.db.begin().await?;
+
+    let user = sqlx::query!(
+        r#"SELECT username, email FROM "users" WHERE id = $1"#,
+        to_sqlx_uuid(user_id),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    let accepted = sqlx::query!(
+        r#"
+            UPDATE "friends"
+            SET accepted_at = now()
+            WHERE user_id = $1 AND friend_id = $2 AND accepted_at IS NULL
+        "#,
+        to_sqlx_uuid(user_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if accepted {
+        tx.commit().await?;
+
+        log::info!(
+            "[add_friend] user {} accepted friend request from {user_id}",
+            auth_user.user_id
+        );
+        return Ok(Json(FriendBody {
+            friend: Friend {
+                id: user_id,
+                username: Some(user.username),
+                email: Some(user.email),
+                status: FriendStatus::Accepted,
+            },
+        })
+        .into_response());
+    }
+
+    let existing = sqlx::query_scalar!(
+        r#"
+            SELECT accepted_at IS NOT NULL as "accepted!"
+            FROM "friends"
+            WHERE
+                least(user_id, friend_id) = least($1::uuid, $2::uuid) AND
+                greatest(user_id, friend_id) = greatest($1::uuid, $2::uuid)
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+        to_sqlx_uuid(user_id),
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    match existing {
+        Some(true) => {
+            return Err(Error::unprocessable_entity([(
+                "user_id",
+                "already friends",
+            )]))
+        }
+        Some(false) => {
+            return Err(Error::unprocessable_entity([(
+                "user_id",
+                "already requested",
+            )]))
+        }
+        None => {}
+    }
+
+    sqlx::query!(
+        r#"INSERT INTO "friends" (user_id, friend_id) VALUES ($1, $2)"#,
+        to_sqlx_uuid(auth_user.user_id),
+        to_sqlx_uuid(user_id),
+    )
+    .execute(&mut *tx)
+    .await
+    // Lost a race with the other user asking at the same time.
+    .on_constraint("friends_pair_key", |_| {
+        Error::unprocessable_entity([("user_id", "already requested")])
+    })?;
+
+    tx.commit().await?;
+
+    Ok(Created::new(FriendBody {
+        friend: Friend {
+            id: user_id,
+            username: None,
+            email: None,
+            status: FriendStatus::Pending,
+        },
+    })
+    .into_response())
+}
+
+/// Unfriend `user_id`, or cancel or decline a pending request between the two.
+async fn remove_friend(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(user_id): Path<uuid::Uuid>,
+) -> Result<()> {
+    let deleted = sqlx::query!(
+        r#"
+            DELETE FROM "friends"
+            WHERE
+                least(user_id, friend_id) = least($1::uuid, $2::uuid) AND
+                greatest(user_id, friend_id) = greatest($1::uuid, $2::uuid)
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+        to_sqlx_uuid(user_id),
+    )
+    .execute(&ctx.db)
+    .await?
+    .rows_affected();
+
+    if deleted == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// List the current user's friends, i.e. users that accepted a request from them or whose
+/// request they accepted.
+async fn list_friends(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    page: Pagination,
+) -> Result<Json<FriendBody<Vec<Friend>>>> {
+    let friend = sqlx::query!(
+        r#"
+            SELECT u.id, u.username, u.email
+            FROM "friends" f
+            INNER JOIN "users" u
+            ON u.id = CASE WHEN f.user_id = $1 THEN f.friend_id ELSE f.user_id END
+            WHERE
+                (f.user_id = $1 OR f.friend_id = $1) AND
+                f.accepted_at IS NOT NULL
+            ORDER BY u.username, u.id
+            LIMIT $2
+            OFFSET $3
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+        page.limit,
+        page.offset,
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|u| Friend {
+        id: to_uuid(u.id),
+        username: Some(u.username),
+        email: Some(u.email),
+        status: FriendStatus::Accepted,
+    })
+    .collect();
+
+    Ok(Json(FriendBody { friend }))
+}
+
+/// List the pending requests other users sent to the current user, newest first.
+///
+/// Accept one with `POST /v1/me/friends/:user_id`, or decline it with `DELETE`.
+async fn list_friend_requests(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    page: Pagination,
+) -> Result<Json<FriendBody<Vec<Friend>>>> {
+    let friend = sqlx::query!(
+        r#"
+            SELECT u.id, u.username, u.email
+            FROM "friends" f
+            INNER JOIN "users" u
+            ON u.id = f.user_id
+            WHERE f.friend_id = $1 AND f.accepted_at IS NULL
+            ORDER BY f.created_at DESC, f.id DESC
+            LIMIT $2
+            OFFSET $3
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+        page.limit,
+        page.offset,
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|u| Friend {
+        id: to_uuid(u.id),
+        username: Some(u.username),
+        email: Some(u.email),
+        status: FriendStatus::Pending,
+    })
+    .collect();
+
+    Ok(Json(FriendBody { friend }))
+}
@@ -1,94 +1,256 @@
 use super::{
     extractor::AuthUser,
-    users::{is_user_in_group, UserBody},
+    types::Created,
+    users::{
+        is_group_admin, is_user_in_group, require_group_role, require_verified_email, UserBody,
+    },
 };
 use crate::{
     commons::{to_sqlx_uuid, to_uuid},
-    dto::group::{Group, GroupBody, NewGroup, UpdateGroup},
-    dto::user::User,
+    dto::group::{
+        Group, GroupBody, GroupRole, GroupSummary, NewGroup, NewMembers, SummaryBody, UpdateGroup,
+        UpdateMemberRole,
+    },
+    dto::ledger::{
+        Balance, BalanceBody, BalanceBreakdown, CounterpartyBalance, Debt, GroupBalances, Ledger,
+        LedgerBody, LedgerEvent, MemberBalance, Settlement, SettlementBody, TotalBalance,
+    },
+    dto::{money::Money, user::User},
     http::{
         error::{Error, ResultExt},
-        ApiContext, Result,
+        ApiContext, Pagination, Result,
     },
-    logic::group::{self, GroupsHandler},
-    logic::ledger,
+    logic::group::GroupsHandler,
+    logic::{currency, ledger, settlement},
 };
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use axum::{
-    extract::{Extension, Path},
-    routing::{get, post},
+    extract::{Extension, Path, Query},
+    routing::{delete, get, post, put},
     Json, Router,
 };
-use futures::stream::StreamExt;
+use futures::stream::TryStreamExt;
 
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
 
 pub fn router() -> Router {
     Router::new()
-        .route("/v1/groups", post(create_group)) // /groups
+        .route("/v1/groups", post(create_group).get(list_groups)) // /groups
+        .route("/v1/groups/search", get(search_groups))
         .route(
             "/v1/groups/:group_id",
-            get(find_group_by_id).put(update_group),
+            get(find_group_by_id).put(update_group).delete(delete_group),
+        )
+        .route("/v1/groups/:group_id/users", get(get_users_by_group))
+        .route("/v1/groups/:group_id/users/batch", post(add_users_to_group))
+        .route(
+            "/v1/groups/:group_id/users/:user_id",
+            delete(remove_user_from_group),
+        )
+        .route(
+            "/v1/groups/:group_id/users/:user_id/role",
+            put(update_member_role),
+        )
+        .route("/v1/groups/:group_id/leave", post(leave_group))
+        .route("/v1/groups/:group_id/balance", get(get_group_balance))
+        .route("/v1/groups/:group_id/me/balance", get(get_my_group_balance))
+        .route("/v1/me/balance", get(get_total_balance))
+        .route("/v1/groups/:group_id/balances", get(get_group_balances))
+        .route("/v1/groups/:group_id/summary", get(get_group_summary))
+        .route(
+            "/v1/groups/:group_id/ledger/:other_user_id",
+            get(get_pairwise_ledger),
+        )
+        .route(
+            "/v1/groups/:group_id/ledger-history",
+            get(get_ledger_history),
+        )
+        .route("/v1/groups/:group_id/settle", get(get_settlements))
+        .route(
+            "/v1/groups/:group_id/settle-suggestions",
+            get(get_settlements),
         )
-        .route("/v1/groups/:group_id/users", post(add_user_to_group))
 }
 
 async fn create_group(
     ctx: Extension<ApiContext>,
     auth_user: AuthUser,
     Json(req): Json<GroupBody<NewGroup>>,
-) -> Result<Json<GroupBody<Group>>> {
-    let handler = group::Handler::new(ctx.db.clone(), ledger::Handler::new());
-    let group = handler.create_group(req.group.name, auth_user).await?;
+) -> Result<Created<GroupBody<Group>>> {
+    require_verified_email(&ctx, auth_user.user_id).await?;
 
-    Ok(Json(GroupBody { group }))
+    let currency = currency::validate_currency(
+        req.group
+            .currency
+            .as_deref()
+            .unwrap_or(currency::DEFAULT_CURRENCY),
+    )?;
+
+    let handler = &ctx.groups;
+    let group = handler
+        .create_group(req.group.name, currency, auth_user)
+        .await?;
+
+    let location = format!("/api/v1/groups/{}", group.id);
+    Ok(Created::new(GroupBody { group }).at(location))
 }
 
 pub async fn get_groups_by_user(
     ctx: Extension<ApiContext>,
     auth_user: AuthUser,
     Path(user_id): Path<uuid::Uuid>,
+    page: Pagination,
 ) -> Result<Json<GroupBody<Vec<Group>>>> {
     if auth_user.user_id != user_id {
         return Err(Error::Forbidden);
     }
-    let groups: Vec<Option<Group>> = sqlx::query!(
+
+    // Group names are unique, but order by `id` as well in case that ever changes,
+    // so that pages never overlap.
+    let groups = sqlx::query!(
         r#"
             SELECT
-                g.id, g.name
+                g.id, g.name, g.currency, g.created_at
             FROM "groups" g
             INNER JOIN "user_groups" ug
             ON g.id = ug.group_id
-            WHERE ug.user_id = $1"#,
+            WHERE ug.user_id = $1
+            ORDER BY g.name, g.id
+            LIMIT $2
+            OFFSET $3"#,
         to_sqlx_uuid(user_id),
+        page.limit,
+        page.offset,
     )
     .fetch(&ctx.db)
-    .map(|g| {
-        g.ok().map(|g| Group {
-            id: to_uuid(g.id),
-            name: g.name,
-        })
+    .map_ok(|g| Group {
+        id: to_uuid(g.id),
+        name: g.name,
+        currency: g.currency,
+        created_at: g.created_at.into(),
     })
-    .collect()
-    .await;
+    .try_collect()
+    .await
+    .with_context(|| format!("failed to get groups of user {user_id}"))?;
 
-    if groups.iter().any(|g| g.is_none()) {
-        log::debug!("[get_groups_by_user] some groups are error");
-        return Err(Error::Anyhow(anyhow!("")));
+    Ok(Json(GroupBody { group: groups }))
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct SearchQuery {
+    #[serde(alias = "query")]
+    q: String,
+}
+
+/// Upper bound on the number of users added to a group in a single batch, so that one request
+/// can't hold a database transaction open for too long.
+const MAX_MEMBERS_BATCH_SIZE: usize = 100;
+
+/// Queries shorter than this are rejected, as they would match nearly everything.
+const MIN_SEARCH_LENGTH: usize = 2;
+
+impl SearchQuery {
+    /// Validate the query and turn it into a pattern for `ILIKE '%' || $1 || '%'`.
+    pub(super) fn to_pattern(&self) -> Result<String> {
+        let q = self.q.trim();
+        if q.chars().count() < MIN_SEARCH_LENGTH {
+            return Err(Error::unprocessable_entity([(
+                "q",
+                format!("must be at least {MIN_SEARCH_LENGTH} characters"),
+            )]));
+        }
+
+        // Match `%` and `_` literally.
+        Ok(q.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_"))
     }
+}
 
-    Ok(Json(GroupBody {
-        group: groups.into_iter().map(Option::unwrap).collect(),
-    }))
+#[derive(serde::Deserialize)]
+struct ListGroupsQuery {
+    /// Only list groups whose name contains this, see `SearchQuery`.
+    #[serde(alias = "query")]
+    q: Option<String>,
 }
 
-pub async fn get_users_by_group(
+/// List the groups the user is a member of, optionally only those with `?query=` in their name.
+async fn list_groups(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Query(query): Query<ListGroupsQuery>,
+    page: Pagination,
+) -> Result<Json<GroupBody<Vec<Group>>>> {
+    let pattern = match query.q {
+        Some(q) => SearchQuery { q }.to_pattern()?,
+        // Matches every name.
+        None => String::new(),
+    };
+
+    find_groups(&ctx, auth_user.user_id, &pattern, page).await
+}
+
+/// Find groups the user is a member of by a case-insensitive substring of their name.
+async fn search_groups(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Query(query): Query<SearchQuery>,
+    page: Pagination,
+) -> Result<Json<GroupBody<Vec<Group>>>> {
+    find_groups(&ctx, auth_user.user_id, &query.to_pattern()?, page).await
+}
+
+/// The groups `user_id` is a member of whose name matches `pattern`, see `SearchQuery::to_pattern`.
+async fn find_groups(
+    ctx: &ApiContext,
+    user_id: uuid::Uuid,
+    pattern: &str,
+    page: Pagination,
+) -> Result<Json<GroupBody<Vec<Group>>>> {
+    // The `case_insensitive` collation doesn't support `ILIKE`, see `migrations/4_user_groups.sql`.
+    let group = sqlx::query!(
+        r#"
+            SELECT
+                g.id, g.name, g.currency, g.created_at
+            FROM "groups" g
+            INNER JOIN "user_groups" ug
+            ON g.id = ug.group_id
+            WHERE
+                ug.user_id = $1 AND
+                (g.name COLLATE "ucs_basic") ILIKE '%' || $2 || '%'
+            ORDER BY g.name, g.id
+            LIMIT $3
+            OFFSET $4"#,
+        to_sqlx_uuid(user_id),
+        pattern,
+        page.limit,
+        page.offset,
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|g| Group {
+        id: to_uuid(g.id),
+        name: g.name,
+        currency: g.currency,
+        created_at: g.created_at.into(),
+    })
+    .collect();
+
+    Ok(Json(GroupBody { group }))
+}
+
+async fn get_users_by_group(
     ctx: Extension<ApiContext>,
     auth_user: AuthUser,
     Path(group_id): Path<uuid::Uuid>,
+    page: Pagination,
 ) -> Result<Json<UserBody<Vec<User>>>> {
-    let handler = group::Handler::new(ctx.db.clone(), ledger::Handler::new());
+    let handler = &ctx.groups;
     if !is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
         .await?
         .0
@@ -97,29 +259,151 @@ pub async fn get_users_by_group(
     }
 
     handler
-        .get_users_by_group(&group_id, None)
+        .get_users_by_group(&group_id, Some(page), None)
         .await
         .map(|user| Json(UserBody { user }))
 }
 
-async fn add_user_to_group(
+/// Add several users to the group at once, e.g. when importing an existing group, and respond
+/// with their membership ids in the order they were given.
+///
+/// Only the group's owner and admins may do this. Either all of the users are added or none are,
+/// see `GroupsHandler::add_users_to_group`.
+async fn add_users_to_group(
     ctx: Extension<ApiContext>,
     auth_user: AuthUser,
     Path(group_id): Path<uuid::Uuid>,
+    Json(req): Json<UserBody<NewMembers>>,
+) -> Result<Json<Vec<uuid::Uuid>>> {
+    let user_ids = req.user.user_ids;
+    if user_ids.is_empty() {
+        return Err(Error::unprocessable_entity([(
+            "user_ids",
+            "must not be empty",
+        )]));
+    }
+    if user_ids.len() > MAX_MEMBERS_BATCH_SIZE {
+        return Err(Error::unprocessable_entity([(
+            "user_ids",
+            format!("at most {MAX_MEMBERS_BATCH_SIZE} users per batch"),
+        )]));
+    }
+    if user_ids.iter().collect::<HashSet<_>>().len() != user_ids.len() {
+        return Err(Error::unprocessable_entity([(
+            "user_ids",
+            "must not contain duplicates",
+        )]));
+    }
+
+    require_verified_email(&ctx, auth_user.user_id).await?;
+    require_group_role(&ctx, auth_user.user_id, group_id, GroupRole::Admin).await?;
+
+    let user_group_ids = ctx
+        .groups
+        .add_users_to_group(&user_ids, &group_id, GroupRole::Member)
+        .await?;
+
+    log::info!(
+        "[add_users_to_group] user {} added {} users to group {group_id}",
+        auth_user.user_id,
+        user_group_ids.len()
+    );
+
+    Ok(Json(user_group_ids))
+}
+
+/// Remove a member from the group, along with their ledger entries in it.
+///
+/// Members may remove themselves, but only the group's owner and admins may remove others. Either way
+/// the removed user has to have settled up first, see `GroupsHandler::remove_user_from_group`.
+async fn remove_user_from_group(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path((group_id, user_id)): Path<(uuid::Uuid, uuid::Uuid)>,
 ) -> Result<Json<uuid::Uuid>> {
-    let handler = group::Handler::new(ctx.db.clone(), ledger::Handler::new());
+    let allowed = if user_id == auth_user.user_id {
+        is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id)).await?
+    } else {
+        is_group_admin(ctx.clone(), Path(auth_user.user_id), Path(group_id)).await?
+    };
+    if !allowed.0 {
+        return Err(Error::Forbidden);
+    }
 
-    handler
-        .add_user_to_group(
-            &auth_user,
-            &Group {
-                id: group_id,
-                name: Default::default(),
-            },
-            None,
-        )
-        .await
-        .map(Json)
+    let handler = &ctx.groups;
+    handler.remove_user_from_group(&user_id, &group_id).await?;
+
+    Ok(Json(user_id))
+}
+
+/// Appoint a member as admin, or demote an admin back to member. Only the group's owner may
+/// do this, and ownership can't be handed over this way.
+async fn update_member_role(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path((group_id, user_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Json(req): Json<UserBody<UpdateMemberRole>>,
+) -> Result<Json<UserBody<User>>> {
+    require_group_role(&ctx, auth_user.user_id, group_id, GroupRole::Owner).await?;
+    if req.user.role == GroupRole::Owner {
+        return Err(Error::unprocessable_entity([(
+            "role",
+            "a group can only have one owner",
+        )]));
+    }
+
+    let user = sqlx::query!(
+        r#"
+            UPDATE "user_groups" ug
+            SET role = $3
+            FROM "users" u
+            WHERE
+                u.id = ug.user_id AND
+                ug.group_id = $1 AND
+                ug.user_id = $2 AND
+                ug.role <> 'OWNER'
+            RETURNING u.username, u.email
+        "#,
+        to_sqlx_uuid(group_id),
+        to_sqlx_uuid(user_id),
+        req.user.role as GroupRole,
+    )
+    .fetch_optional(&ctx.db)
+    .await?;
+
+    // Nothing was updated, so either the user isn't in the group or it's the owner themselves.
+    let Some(user) = user else {
+        if is_user_in_group(ctx.clone(), Path(user_id), Path(group_id))
+            .await?
+            .0
+        {
+            return Err(Error::unprocessable_entity([(
+                "role",
+                "the group's owner can't change their own role",
+            )]));
+        }
+        return Err(Error::NotFound);
+    };
+
+    Ok(Json(UserBody {
+        user: User {
+            id: user_id,
+            email: user.email,
+            username: user.username,
+            role: req.user.role,
+        },
+    }))
+}
+
+async fn leave_group(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<uuid::Uuid>> {
+    let handler = &ctx.groups;
+    handler.leave_group(&auth_user, &group_id).await?;
+
+    Ok(Json(group_id))
 }
 
 async fn find_group_by_id(
@@ -135,14 +419,16 @@ async fn find_group_by_id(
     }
 
     // is user in the group?
-    let Json(g) = get_groups_by_user(ctx.clone(), auth_user, Path(auth_user.user_id)).await?;
-    if !g.group.iter().any(|g| g.id == group_id) {
+    if !is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
         return Err(Error::Unauthorized);
     }
 
-    let group_name = sqlx::query_scalar!(
+    let group = sqlx::query!(
         r#"
-         SELECT name
+         SELECT name, currency, created_at
          FROM "groups"
          WHERE id=$1
          "#,
@@ -158,14 +444,107 @@ async fn find_group_by_id(
     Ok(Json(GroupBody {
         group: Group {
             id: group_id,
-            name: group_name,
+            name: group.name,
+            currency: group.currency,
+            created_at: group.created_at.into(),
         },
     }))
 }
 
+/// Delete a group along with its members, ledgers, transactions, recurring transactions and
+/// categories. Only the group's owner may do this.
+async fn delete_group(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<GroupBody<Group>>> {
+    let mut tx = ctx.db.begin().await?;
+
+    // Lock the group so no one can join it or transact in it while it's being deleted.
+    let group = sqlx::query!(
+        r#"
+            SELECT
+                name,
+                currency,
+                created_at,
+                EXISTS (
+                    SELECT 1 FROM "user_groups"
+                    WHERE group_id = $1 AND user_id = $2 AND role = 'OWNER'
+                ) AS "is_owner!"
+            FROM "groups"
+            WHERE id = $1
+            FOR UPDATE
+        "#,
+        to_sqlx_uuid(group_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if !group.is_owner {
+        return Err(Error::Forbidden);
+    }
+
+    // Delete in dependency order; `transactions` goes before `categories` so that deleting
+    // categories doesn't have to null out rows that are about to be deleted anyway.
+    for query in [
+        sqlx::query!(
+            r#"DELETE FROM "transactions" WHERE group_id = $1"#,
+            to_sqlx_uuid(group_id)
+        ),
+        sqlx::query!(
+            r#"DELETE FROM "recurring_transactions" WHERE group_id = $1"#,
+            to_sqlx_uuid(group_id)
+        ),
+        sqlx::query!(
+            r#"DELETE FROM "categories" WHERE group_id = $1"#,
+            to_sqlx_uuid(group_id)
+        ),
+        sqlx::query!(
+            r#"DELETE FROM "ledgers" WHERE group_id = $1"#,
+            to_sqlx_uuid(group_id)
+        ),
+        sqlx::query!(
+            r#"DELETE FROM "ledger_events" WHERE group_id = $1"#,
+            to_sqlx_uuid(group_id)
+        ),
+        sqlx::query!(
+            r#"DELETE FROM "user_groups" WHERE group_id = $1"#,
+            to_sqlx_uuid(group_id)
+        ),
+        sqlx::query!(
+            r#"DELETE FROM "groups" WHERE id = $1"#,
+            to_sqlx_uuid(group_id)
+        ),
+    ] {
+        query.execute(&mut *tx).await?;
+    }
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[delete_group] fail to commit db transaction: {e}");
+        Error::Anyhow(anyhow!(""))
+    })?;
+
+    Ok(Json(GroupBody {
+        group: Group {
+            id: group_id,
+            name: group.name,
+            currency: group.currency,
+            created_at: group.created_at.into(),
+        },
+    }))
+}
+
+/// Rename a group. Only the group's owner and admins may do this.
+///
+/// Fields left out are kept as they are, but at least one has to be sent, so `{"group": {}}` is
+/// rejected. A group always has a name, so `{"group": {"name": null}}` is rejected too rather than
+/// being treated as leaving the name out.
 async fn update_group(
     Path(group_id): Path<String>,
     ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
     Json(req): Json<GroupBody<UpdateGroup>>,
 ) -> Result<Json<GroupBody<Group>>> {
     if group_id.is_empty() {
@@ -177,11 +556,15 @@ async fn update_group(
     if req.group == UpdateGroup::default() {
         return Err(Error::unprocessable_entity([("all", "all fields empty")]));
     }
+    let Some(name) = req.group.name.flatten() else {
+        return Err(Error::unprocessable_entity([("name", "cannot be cleared")]));
+    };
 
     let group_id = sqlx::types::Uuid::from_str(&group_id).map_err(|e| {
         log::debug!("failed to convert string to uuid: {e}");
         Error::unprocessable_entity([("group_id", "invalid group id")])
     })?;
+    require_group_role(&ctx, auth_user.user_id, to_uuid(group_id), GroupRole::Admin).await?;
 
     let group = sqlx::query!(
         // Optional updates of fields without needing a separate query for each.
@@ -189,10 +572,10 @@ async fn update_group(
             update "groups"
             set name = coalesce($2, "groups".name)
             where id = $1
-            returning name
+            returning name, currency, created_at
         "#,
         group_id,
-        req.group.name,
+        name,
     )
     .fetch_one(&ctx.db)
     .await
@@ -204,6 +587,616 @@ async fn update_group(
         group: Group {
             id: to_uuid(group_id),
             name: group.name,
+            currency: group.currency,
+            created_at: group.created_at.into(),
+        },
+    }))
+}
+
+async fn get_group_balance(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<BalanceBody<Balance>>> {
+    if !is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
+        return Err(Error::Forbidden);
+    }
+
+    let group_currency = sqlx::query_scalar!(
+        r#"SELECT currency FROM "groups" WHERE id = $1"#,
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    // `sum` over a `bigint` column returns `numeric`, so cast it back.
+    let rows = sqlx::query!(
+        r#"
+            SELECT currency, sum(amount)::bigint as "net!"
+            FROM "ledgers"
+            WHERE group_id = $1 AND this_user = $2
+            GROUP BY currency
+        "#,
+        to_sqlx_uuid(group_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?;
+
+    Ok(Json(BalanceBody {
+        balance: to_balance(
+            &ctx,
+            group_id,
+            auth_user.user_id,
+            group_currency,
+            rows.into_iter().map(|row| (row.currency, row.net)),
+        )?,
+    }))
+}
+
+/// The current user's net position in a group, broken down by the members they owe or are owed
+/// by, e.g. to show "you are owed RM30 in this group" along with by whom.
+async fn get_my_group_balance(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<BalanceBody<BalanceBreakdown>>> {
+    if !is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
+        return Err(Error::Forbidden);
+    }
+
+    let group_currency = sqlx::query_scalar!(
+        r#"SELECT currency FROM "groups" WHERE id = $1"#,
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    let entries = sqlx::query!(
+        r#"
+            SELECT l.other_user, u.username, l.currency, l.amount
+            FROM "ledgers" l
+            INNER JOIN "users" u ON u.id = l.other_user
+            WHERE l.group_id = $1 AND l.this_user = $2 AND l.amount <> 0
+            ORDER BY u.username, l.other_user, l.currency
+        "#,
+        to_sqlx_uuid(group_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?;
+
+    let balance = to_balance(
+        &ctx,
+        group_id,
+        auth_user.user_id,
+        group_currency.clone(),
+        entries.iter().map(|e| (e.currency.clone(), e.amount)),
+    )?;
+
+    // Entries are ordered by member, so each member's entries are next to each other.
+    let mut members: Vec<CounterpartyBalance> = vec![];
+    for member_entries in entries.chunk_by(|a, b| a.other_user == b.other_user) {
+        let Balance {
+            net, by_currency, ..
+        } = to_balance(
+            &ctx,
+            group_id,
+            to_uuid(member_entries[0].other_user),
+            group_currency.clone(),
+            member_entries
+                .iter()
+                .map(|e| (e.currency.clone(), e.amount)),
+        )?;
+
+        members.push(CounterpartyBalance {
+            user_id: to_uuid(member_entries[0].other_user),
+            username: member_entries[0].username.clone(),
+            net,
+            by_currency,
+        });
+    }
+
+    Ok(Json(BalanceBody {
+        balance: BalanceBreakdown { balance, members },
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct TotalBalanceQuery {
+    /// The currency to show the total in, defaults to `currency::DEFAULT_CURRENCY`.
+    #[serde(rename = "in")]
+    in_currency: Option<String>,
+}
+
+/// The current user's net position summed over every group they're in, along with their
+/// balance in each of those groups.
+async fn get_total_balance(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Query(query): Query<TotalBalanceQuery>,
+) -> Result<Json<BalanceBody<TotalBalance>>> {
+    let display_currency = match query.in_currency {
+        Some(code) => currency::validate_currency(&code)?,
+        None => currency::DEFAULT_CURRENCY.to_string(),
+    };
+
+    // `sum` over a `bigint` column returns `numeric`, so cast it back.
+    let rows = sqlx::query!(
+        r#"
+            SELECT
+                l.group_id, g.currency as group_currency, l.currency,
+                sum(l.amount)::bigint as "net!"
+            FROM "ledgers" l
+            INNER JOIN "groups" g ON g.id = l.group_id
+            INNER JOIN "user_groups" ug ON ug.group_id = l.group_id AND ug.user_id = l.this_user
+            WHERE l.this_user = $1
+            GROUP BY l.group_id, g.currency, l.currency
+            ORDER BY l.group_id, l.currency
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?;
+
+    let mut groups: BTreeMap<uuid::Uuid, (String, Vec<(String, i64)>)> = BTreeMap::new();
+    let mut by_currency: HashMap<String, Money> = HashMap::new();
+    for row in rows {
+        ledger::add_amount(
+            by_currency.entry(row.currency.clone()).or_default(),
+            Money::from_minor(row.net),
+        )?;
+        groups
+            .entry(to_uuid(row.group_id))
+            .or_insert_with(|| (row.group_currency, vec![]))
+            .1
+            .push((row.currency, row.net));
+    }
+    by_currency.retain(|_, amount| amount.minor() != 0);
+
+    let mut total_net = Money::default();
+    for (currency, amount) in &by_currency {
+        ledger::add_amount(
+            &mut total_net,
+            Money::from_minor(currency::convert(
+                ctx.rates.as_ref(),
+                amount.minor(),
+                currency,
+                &display_currency,
+            )?),
+        )?;
+    }
+
+    let per_group = groups
+        .into_iter()
+        .map(|(group_id, (group_currency, nets))| {
+            to_balance(&ctx, group_id, auth_user.user_id, group_currency, nets)
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Json(BalanceBody {
+        balance: TotalBalance {
+            user_id: auth_user.user_id,
+            currency: display_currency,
+            total_net,
+            by_currency,
+            per_group,
+        },
+    }))
+}
+
+/// Build a user's `Balance` from their net position per currency.
+fn to_balance(
+    ctx: &ApiContext,
+    group_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+    group_currency: String,
+    nets: impl IntoIterator<Item = (String, i64)>,
+) -> Result<Balance> {
+    let mut net = Money::default();
+    let mut by_currency: HashMap<String, Money> = HashMap::new();
+    for (currency, amount) in nets {
+        ledger::add_amount(
+            by_currency.entry(currency).or_default(),
+            Money::from_minor(amount),
+        )?;
+    }
+    by_currency.retain(|_, amount| amount.minor() != 0);
+    for (currency, amount) in &by_currency {
+        ledger::add_amount(
+            &mut net,
+            Money::from_minor(currency::convert(
+                ctx.rates.as_ref(),
+                amount.minor(),
+                currency,
+                &group_currency,
+            )?),
+        )?;
+    }
+
+    Ok(Balance {
+        group_id,
+        user_id,
+        currency: group_currency,
+        net,
+        by_currency,
+    })
+}
+
+/// Everything needed to show a group's page in one go: the group, its members, and where the
+/// current user stands with each of them.
+async fn get_group_summary(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<SummaryBody<GroupSummary>>> {
+    if !is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
+        return Err(Error::Forbidden);
+    }
+
+    let group = sqlx::query!(
+        r#"SELECT name, currency, created_at FROM "groups" WHERE id = $1"#,
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    let members = ctx.groups.get_users_by_group(&group_id, None, None).await?;
+
+    let entries = sqlx::query!(
+        r#"
+            SELECT other_user, currency, amount
+            FROM "ledgers"
+            WHERE group_id = $1 AND this_user = $2
+            ORDER BY other_user, currency
+        "#,
+        to_sqlx_uuid(group_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?;
+
+    let my_balance = to_balance(
+        &ctx,
+        group_id,
+        auth_user.user_id,
+        group.currency.clone(),
+        entries.iter().map(|e| (e.currency.clone(), e.amount)),
+    )?;
+    let pairwise_balances = entries
+        .into_iter()
+        .filter(|e| e.amount != 0)
+        .map(|e| Ledger {
+            this_user: auth_user.user_id,
+            other_user: to_uuid(e.other_user),
+            currency: e.currency,
+            amount: Money::from_minor(e.amount),
+        })
+        .collect();
+
+    Ok(Json(SummaryBody {
+        summary: GroupSummary {
+            group: Group {
+                id: group_id,
+                name: group.name,
+                currency: group.currency,
+                created_at: group.created_at.into(),
+            },
+            members,
+            my_balance,
+            pairwise_balances,
+        },
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct LedgerQuery {
+    /// The currency of the ledger entry, defaults to the group's currency.
+    currency: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct LedgerHistoryQuery {
+    /// Only list changes to this member's side of their ledger entries.
+    user_id: Option<uuid::Uuid>,
+}
+
+/// List every change to the group's ledger entries, newest first, to see how the balances got
+/// to where they are.
+async fn get_ledger_history(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+    Query(query): Query<LedgerHistoryQuery>,
+    page: Pagination,
+) -> Result<Json<LedgerBody<Vec<LedgerEvent>>>> {
+    if !is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
+        return Err(Error::Forbidden);
+    }
+
+    let ledger = sqlx::query!(
+        r#"
+            SELECT id, this_user, other_user, currency, delta, caused_by_tx_id, created_at
+            FROM "ledger_events"
+            WHERE group_id = $1 AND ($2::uuid IS NULL OR this_user = $2)
+            ORDER BY created_at DESC, id DESC
+            LIMIT $3
+            OFFSET $4
+        "#,
+        to_sqlx_uuid(group_id),
+        query.user_id.map(to_sqlx_uuid),
+        page.limit,
+        page.offset,
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|e| LedgerEvent {
+        id: to_uuid(e.id),
+        this_user: to_uuid(e.this_user),
+        other_user: to_uuid(e.other_user),
+        currency: e.currency,
+        delta: Money::from_minor(e.delta),
+        caused_by_tx_id: to_uuid(e.caused_by_tx_id),
+        created_at: e.created_at.into(),
+    })
+    .collect();
+
+    Ok(Json(LedgerBody { ledger }))
+}
+
+async fn get_pairwise_ledger(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path((group_id, other_user_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Query(query): Query<LedgerQuery>,
+) -> Result<Json<LedgerBody<Ledger>>> {
+    for user_id in [auth_user.user_id, other_user_id] {
+        if !is_user_in_group(ctx.clone(), Path(user_id), Path(group_id))
+            .await?
+            .0
+        {
+            return Err(Error::Forbidden);
+        }
+    }
+
+    let currency = match query.currency {
+        Some(code) => currency::validate_currency(&code)?,
+        None => {
+            sqlx::query_scalar!(
+                r#"SELECT currency FROM "groups" WHERE id = $1"#,
+                to_sqlx_uuid(group_id),
+            )
+            .fetch_one(&ctx.db)
+            .await?
+        }
+    };
+
+    // There is no ledger between a user and themselves.
+    if other_user_id == auth_user.user_id {
+        return Err(Error::NotFound);
+    }
+
+    // The pair has no entry yet in a currency it never used, see
+    // `ledger::Handler::update_ledger_entries`.
+    let amount = sqlx::query_scalar!(
+        r#"
+            SELECT amount
+            FROM "ledgers"
+            WHERE
+                group_id = $1 AND
+                this_user = $2 AND
+                other_user = $3 AND
+                currency = $4
+        "#,
+        to_sqlx_uuid(group_id),
+        to_sqlx_uuid(auth_user.user_id),
+        to_sqlx_uuid(other_user_id),
+        currency,
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .unwrap_or(0);
+
+    Ok(Json(LedgerBody {
+        ledger: Ledger {
+            this_user: auth_user.user_id,
+            other_user: other_user_id,
+            currency,
+            amount: Money::from_minor(amount),
         },
     }))
 }
+
+/// Suggest the payments that would settle all debts within the group, per currency.
+///
+/// This only computes the suggestions, nothing is recorded. Served under both `/settle` and
+/// `/settle-suggestions`.
+async fn get_settlements(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<SettlementBody<BTreeMap<String, Vec<Settlement>>>>> {
+    if !is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
+        return Err(Error::Forbidden);
+    }
+
+    let rows = sqlx::query!(
+        r#"
+            SELECT this_user, currency, sum(amount)::bigint as "net!"
+            FROM "ledgers"
+            WHERE group_id = $1
+            GROUP BY this_user, currency
+        "#,
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?;
+
+    let mut balances: BTreeMap<String, Vec<(uuid::Uuid, i64)>> = BTreeMap::new();
+    for row in rows {
+        balances
+            .entry(row.currency)
+            .or_default()
+            .push((to_uuid(row.this_user), row.net));
+    }
+
+    Ok(Json(SettlementBody {
+        settlement: balances
+            .into_iter()
+            .map(|(currency, balances)| (currency, settlement::simplify_debts(balances)))
+            .filter(|(_, settlements)| !settlements.is_empty())
+            .collect(),
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct BalancesQuery {
+    /// The currency to show balances in, defaults to the group's currency.
+    #[serde(rename = "in")]
+    in_currency: Option<String>,
+}
+
+/// Show every member's net position in the group, across all currencies the group
+/// transacted in, along with who owes whom.
+async fn get_group_balances(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+    Query(query): Query<BalancesQuery>,
+) -> Result<Json<BalanceBody<GroupBalances>>> {
+    if !is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
+        return Err(Error::Forbidden);
+    }
+
+    let display_currency = match query.in_currency {
+        Some(code) => currency::validate_currency(&code)?,
+        None => {
+            sqlx::query_scalar!(
+                r#"SELECT currency FROM "groups" WHERE id = $1"#,
+                to_sqlx_uuid(group_id),
+            )
+            .fetch_one(&ctx.db)
+            .await?
+        }
+    };
+
+    let rows = sqlx::query!(
+        r#"
+            SELECT this_user, other_user, currency, amount
+            FROM "ledgers"
+            WHERE group_id = $1
+            ORDER BY this_user, other_user, currency
+        "#,
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?;
+
+    // Use a `BTreeMap` so members are always listed in the same order.
+    let mut members: BTreeMap<uuid::Uuid, MemberBalance> = BTreeMap::new();
+    let mut debts = vec![];
+    for row in rows {
+        let user_id = to_uuid(row.this_user);
+        let member = members.entry(user_id).or_insert_with(|| MemberBalance {
+            user_id,
+            net: Money::default(),
+            by_currency: Default::default(),
+        });
+        if row.amount == 0 {
+            continue;
+        }
+
+        ledger::add_amount(
+            &mut member.net,
+            Money::from_minor(currency::convert(
+                ctx.rates.as_ref(),
+                row.amount,
+                &row.currency,
+                &display_currency,
+            )?),
+        )?;
+        ledger::add_amount(
+            member.by_currency.entry(row.currency.clone()).or_default(),
+            Money::from_minor(row.amount),
+        )?;
+
+        // Every pair has an entry in both directions, so only take the debtor's side.
+        if row.amount < 0 {
+            debts.push(Debt {
+                from: user_id,
+                to: to_uuid(row.other_user),
+                currency: row.currency,
+                amount: Money::from_minor(-row.amount),
+            });
+        }
+    }
+    for member in members.values_mut() {
+        member.by_currency.retain(|_, net| net.minor() != 0);
+    }
+
+    Ok(Json(BalanceBody {
+        balance: GroupBalances {
+            group_id,
+            currency: display_currency,
+            members: members.into_values().collect(),
+            debts,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, StatusCode};
+    use sqlx::{Pool, Postgres};
+
+    #[sqlx::test]
+    async fn only_admins_may_rename_a_group(db: Pool<Postgres>) {
+        let ctx = ApiContext::for_tests(db);
+        let (_, alice) = ctx.sign_up("alice").await;
+        let (bob_id, bob) = ctx.sign_up("bob").await;
+        let group_id = ctx.create_group(&alice, &[bob_id]).await;
+        let uri = format!("/api/v1/groups/{group_id}");
+        let rename = |name: &str| serde_json::json!({ "group": { "name": name } });
+
+        let (status, body) = ctx
+            .request(Method::PUT, &uri, Some(&bob), Some(rename("bob's trip")))
+            .await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{body}");
+
+        let (status, body) = ctx.request(Method::GET, &uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["group"]["name"], "trip");
+
+        let (status, body) = ctx
+            .request(
+                Method::PUT,
+                &uri,
+                Some(&alice),
+                Some(rename("alice's trip")),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["group"]["name"], "alice's trip");
+    }
+}
@@ -0,0 +1,74 @@
+use crate::http::{ApiContext, Result};
+
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use std::time::Duration;
+
+/// How long the readiness probe waits on the database before reporting it unreachable.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Liveness and readiness probes for the load balancer, and what version is deployed. These are
+/// mounted outside of `/api` and don't require authentication.
+pub fn router() -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/version", get(version))
+}
+
+#[derive(serde::Serialize)]
+struct Version {
+    /// The version of this crate, from `Cargo.toml`.
+    version: &'static str,
+    /// The latest migration applied to the database, `None` if there are none.
+    migration_version: Option<i64>,
+}
+
+async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+async fn readyz(ctx: Extension<ApiContext>) -> Response {
+    let probe = sqlx::query("SELECT 1").execute(&ctx.db);
+
+    match tokio::time::timeout(READINESS_TIMEOUT, probe).await {
+        Ok(Ok(_)) => StatusCode::OK.into_response(),
+        Ok(Err(e)) => {
+            log::error!("[readyz] database unreachable: {e}");
+            unavailable("database unreachable")
+        }
+        Err(_) => {
+            log::error!("[readyz] database probe timed out");
+            unavailable("database probe timed out")
+        }
+    }
+}
+
+/// Lets operators check that a deploy is live and that it ran its migrations, see `sqlx::migrate!`.
+async fn version(ctx: Extension<ApiContext>) -> Result<Json<Version>> {
+    // Not `query_scalar!`, since `_sqlx_migrations` is created by the migrator rather than by
+    // a migration.
+    let migration_version = sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT max(version) FROM _sqlx_migrations WHERE success",
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    Ok(Json(Version {
+        version: env!("CARGO_PKG_VERSION"),
+        migration_version,
+    }))
+}
+
+fn unavailable(reason: &'static str) -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "status": "unavailable", "reason": reason })),
+    )
+        .into_response()
+}
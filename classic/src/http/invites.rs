@@ -0,0 +1,190 @@
+use super::{
+    extractor::AuthUser,
+    types::{Created, Timestamptz},
+    users::{generate_token, hash_token, require_group_role, require_verified_email},
+};
+use crate::{
+    commons::{to_sqlx_uuid, to_uuid},
+    dto::group::{Group, GroupBody, GroupRole},
+    http::{error::Error, ApiContext, Result},
+    logic::group::GroupsHandler,
+};
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Extension, Path},
+    routing::post,
+    Json, Router,
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/v1/groups/:group_id/invites", post(create_invite))
+        .route("/v1/groups/join", post(join_group))
+        .route("/v1/invites/:token/accept", post(accept_invite))
+}
+
+/// A wrapper type for all requests/responses from this module.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct InviteBody<T> {
+    invite: T,
+}
+
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct NewInvite {
+    /// How many users can join with the invite, defaults to one.
+    max_uses: Option<i32>,
+}
+
+#[derive(serde::Deserialize)]
+struct JoinGroup {
+    token: String,
+}
+
+#[derive(serde::Serialize)]
+struct Invite {
+    group_id: uuid::Uuid,
+    /// Only ever returned when the invite is created, as only its hash is stored.
+    token: String,
+    max_uses: i32,
+    expires_at: Timestamptz,
+}
+
+/// Create an invite to the group that can be shared with up to `max_uses` users.
+/// Only the group's owner and admins may do this.
+///
+/// There's no email integration yet, so it's up to them to pass the token on.
+async fn create_invite(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+    req: Option<Json<InviteBody<NewInvite>>>,
+) -> Result<Created<InviteBody<Invite>>> {
+    require_group_role(&ctx, auth_user.user_id, group_id, GroupRole::Admin).await?;
+
+    let max_uses = req.and_then(|Json(req)| req.invite.max_uses).unwrap_or(1);
+    if max_uses < 1 {
+        return Err(Error::unprocessable_entity([(
+            "max_uses",
+            "must be positive",
+        )]));
+    }
+
+    let token = generate_token();
+
+    let expires_at = sqlx::query_scalar!(
+        r#"
+            insert into "invites" (group_id, created_by, token_hash, max_uses, expires_at)
+            values ($1, $2, $3, $4, now() + make_interval(secs => $5::bigint))
+            returning expires_at
+        "#,
+        to_sqlx_uuid(group_id),
+        to_sqlx_uuid(auth_user.user_id),
+        hash_token(&token),
+        max_uses,
+        ctx.config.invite_ttl_seconds,
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    log::info!(
+        "[create_invite] user {} invited to group {group_id}",
+        auth_user.user_id
+    );
+
+    Ok(Created::new(InviteBody {
+        invite: Invite {
+            group_id,
+            token,
+            max_uses,
+            expires_at: expires_at.into(),
+        },
+    }))
+}
+
+/// Join the group an invite is for, using up one of its uses.
+///
+/// This is the only way to join a group other than being added by one of its admins.
+async fn join_group(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Json(req): Json<InviteBody<JoinGroup>>,
+) -> Result<Json<GroupBody<Group>>> {
+    join_with_invite(&ctx, auth_user, &req.invite.token).await
+}
+
+/// Same as `join_group`, for clients that still pass the token in the URL.
+async fn accept_invite(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(token): Path<String>,
+) -> Result<Json<GroupBody<Group>>> {
+    join_with_invite(&ctx, auth_user, &token).await
+}
+
+async fn join_with_invite(
+    ctx: &ApiContext,
+    auth_user: AuthUser,
+    token: &str,
+) -> Result<Json<GroupBody<Group>>> {
+    require_verified_email(ctx, auth_user.user_id).await?;
+
+    let mut tx = ctx.db.begin().await?;
+
+    // Counting the use up front, with the row locked until the end of the transaction, keeps
+    // concurrent requests from going over `max_uses`. Any error below rolls the use back.
+    let invite = sqlx::query!(
+        r#"
+            update "invites"
+            set uses = uses + 1
+            where token_hash = $1
+            returning
+                group_id,
+                expires_at > now() as "unexpired!",
+                uses <= max_uses as "available!"
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if !invite.unexpired {
+        return Err(Error::unprocessable_entity([("token", "invite expired")]));
+    }
+    if !invite.available {
+        return Err(Error::unprocessable_entity([("token", "invite used up")]));
+    }
+
+    let group = sqlx::query!(
+        r#"
+            select name, currency, created_at
+            from "groups"
+            where id = $1
+        "#,
+        invite.group_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    let group = Group {
+        id: to_uuid(invite.group_id),
+        name: group.name,
+        currency: group.currency,
+        created_at: group.created_at.into(),
+    };
+
+    // Fails if the user is already a member, rolling back the use of the invite.
+    ctx.groups
+        .add_user_to_group(&auth_user, &group.id, GroupRole::Member, Some(&mut tx))
+        .await?;
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[join_with_invite] fail to commit db transaction: {e}");
+        Error::Anyhow(anyhow!(""))
+    })?;
+
+    Ok(Json(GroupBody { group }))
+}
@@ -1,7 +1,22 @@
-use crate::config::Config;
+use crate::{
+    config::Config,
+    logic::{
+        avatar::{AvatarProvider, HttpAvatarProvider},
+        currency::{RatesProvider, StaticRates},
+        group, ledger,
+        mailer::{LogMailer, Mailer},
+        oauth::{GoogleIdTokenVerifier, IdTokenVerifier},
+        rate_limit::{InMemoryRateLimiter, RateLimiter},
+    },
+};
 
 use anyhow::Context;
-use axum::{extract::Extension, Router};
+use async_trait::async_trait;
+use axum::{
+    extract::{Extension, FromRequestParts, Query},
+    Router,
+};
+use http::request::Parts;
 use sqlx::PgPool;
 use tower::ServiceBuilder;
 use tower_http::{
@@ -9,7 +24,7 @@ use tower_http::{
     ServiceBuilderExt,
 };
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 // Utility modules.
 
@@ -35,15 +50,22 @@ mod types;
 // are more stream-of-consciousness and assume you read them in a particular order.
 //
 // See `api_router()` below for the recommended order.
+mod api_keys;
+mod categories;
+mod friends;
 mod groups;
+mod health;
+mod invites;
+mod recurring;
 mod transactions;
 mod users;
 
 pub use error::{Error, ResultExt};
+pub use types::Timestamptz;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
+use tower_http::trace::{DefaultOnResponse, MakeSpan, TraceLayer};
 
 /// The core type through which handler functions can access common API state.
 ///
@@ -62,6 +84,221 @@ use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
 pub struct ApiContext {
     config: Arc<Config>,
     db: PgPool,
+    /// Exchange rates used to show balances across currencies.
+    rates: Arc<dyn RatesProvider>,
+    /// Group membership logic, constructed once at startup and shared by all requests.
+    groups: Arc<group::Handler>,
+    /// Ledger bookkeeping, shared like `groups`.
+    ledger: Arc<ledger::Handler>,
+    /// Sends emails to users.
+    mailer: Arc<dyn Mailer>,
+    /// Fetches avatars for users who haven't uploaded one.
+    avatars: Arc<dyn AvatarProvider>,
+    /// Locks out emails and IP addresses after too many failed logins.
+    login_limiter: Arc<dyn RateLimiter>,
+    /// Checks the ID tokens of users logging in with Google.
+    google: Arc<dyn IdTokenVerifier>,
+}
+
+impl ApiContext {
+    /// Set up the state shared by all requests, talking to the real external services.
+    fn new(config: Config, db: PgPool) -> Self {
+        let avatars = HttpAvatarProvider::new(
+            config.avatar_base_url.clone(),
+            config.avatar_path_template.clone(),
+        );
+
+        let google = GoogleIdTokenVerifier::new(
+            config.google_jwks_url.clone(),
+            config.google_client_ids.clone(),
+        );
+
+        let login_limiter = InMemoryRateLimiter::new(
+            Duration::from_secs(config.login_failure_window_seconds),
+            Duration::from_secs(config.login_lockout_seconds),
+        );
+
+        Self {
+            config: Arc::new(config),
+            groups: Arc::new(group::Handler::new(db.clone(), ledger::Handler::new())),
+            db,
+            rates: Arc::new(StaticRates::default()),
+            ledger: Arc::new(ledger::Handler::new()),
+            mailer: Arc::new(LogMailer),
+            avatars: Arc::new(avatars),
+            login_limiter: Arc::new(login_limiter),
+            google: Arc::new(google),
+        }
+    }
+
+    /// A context for tests, with the default configuration and nothing reaching out to
+    /// external services.
+    #[cfg(test)]
+    fn for_tests(db: PgPool) -> Self {
+        use clap::Parser;
+
+        let config = Config::parse_from([
+            "test",
+            "--database-url=",
+            "--hmac-key=test-hmac-key",
+            "--avatar-fetch-enabled=false",
+            "--recurring-enabled=false",
+        ]);
+
+        Self::new(config, db)
+    }
+
+    /// Send a request through the whole API as a test, authenticated with `token` if given,
+    /// and return the response status and JSON body, or `null` if there is none.
+    #[cfg(test)]
+    async fn request(
+        &self,
+        method: http::Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> (http::StatusCode, serde_json::Value) {
+        use axum::extract::ConnectInfo;
+        use tower::ServiceExt;
+
+        let mut req = http::Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            req = req.header(http::header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let mut req = match body {
+            Some(body) => req
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(hyper::Body::from(body.to_string())),
+            None => req.body(hyper::Body::empty()),
+        }
+        .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+
+        let res = api_router()
+            .layer(Extension(self.clone()))
+            .oneshot(req)
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = if body.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap()
+        };
+
+        (status, body)
+    }
+
+    /// Sign up a user as a test, returning their id and login token.
+    #[cfg(test)]
+    async fn sign_up(&self, username: &str) -> (uuid::Uuid, String) {
+        let (status, body) = self
+            .request(
+                http::Method::POST,
+                "/api/v1/users",
+                None,
+                Some(serde_json::json!({
+                    "user": {
+                        "username": username,
+                        "email": format!("{username}@example.com"),
+                        "password": "password123",
+                    }
+                })),
+            )
+            .await;
+        assert_eq!(status, http::StatusCode::CREATED, "{body}");
+
+        (
+            body["user"]["id"].as_str().unwrap().parse().unwrap(),
+            body["user"]["token"].as_str().unwrap().to_string(),
+        )
+    }
+
+    /// Create a group in MYR as a test, owned by the user with `token` and with `members`
+    /// added to it, returning its id.
+    #[cfg(test)]
+    async fn create_group(&self, token: &str, members: &[uuid::Uuid]) -> uuid::Uuid {
+        let (status, body) = self
+            .request(
+                http::Method::POST,
+                "/api/v1/groups",
+                Some(token),
+                Some(serde_json::json!({ "group": { "name": "trip", "currency": "MYR" } })),
+            )
+            .await;
+        assert_eq!(status, http::StatusCode::CREATED, "{body}");
+        let group_id = body["group"]["id"].as_str().unwrap().to_string();
+
+        if !members.is_empty() {
+            let (status, body) = self
+                .request(
+                    http::Method::POST,
+                    &format!("/api/v1/groups/{group_id}/users/batch"),
+                    Some(token),
+                    Some(serde_json::json!({ "user": { "user_ids": members } })),
+                )
+                .await;
+            assert_eq!(status, http::StatusCode::OK, "{body}");
+        }
+
+        group_id.parse().unwrap()
+    }
+}
+
+/// Default page size for paginated listings.
+const DEFAULT_PAGE_LIMIT: i64 = 50;
+
+/// Upper bound on the page size for paginated listings, so that a client can't ask for
+/// an unbounded scan of a table.
+const MAX_PAGE_LIMIT: i64 = 200;
+
+/// Add this as a parameter to a handler function to page through a listing with the
+/// `?limit=<n>&offset=<n>` query parameters.
+///
+/// Both are optional, and out-of-range values are clamped rather than rejected.
+/// Handlers using this must return results in a stable order so that pages don't overlap.
+#[derive(Debug, Clone, Copy)]
+pub struct Pagination {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(serde::Deserialize)]
+struct PaginationQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_PAGE_LIMIT,
+            offset: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<()> for Pagination {
+    type Rejection = Error;
+
+    async fn from_request_parts(req: &mut Parts, s: &()) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<PaginationQuery>::from_request_parts(req, s)
+            .await
+            .map_err(|_| {
+                Error::unprocessable_entity([("pagination", "limit and offset must be integers")])
+            })?;
+
+        Ok(Self {
+            limit: query
+                .limit
+                .unwrap_or(DEFAULT_PAGE_LIMIT)
+                .clamp(1, MAX_PAGE_LIMIT),
+            offset: query.offset.unwrap_or(0).max(0),
+        })
+    }
 }
 
 #[derive(Clone, Default)]
@@ -75,7 +312,38 @@ impl MakeRequestId for UuidRequestId {
     }
 }
 
+/// Puts each request's `X-Request-Id` on its span, so every log line emitted while handling
+/// it can be correlated.
+#[derive(Clone, Default)]
+struct RequestSpan;
+
+impl<B> MakeSpan<B> for RequestSpan {
+    fn make_span(&mut self, req: &http::Request<B>) -> tracing::Span {
+        // Set by `set_x_request_id()` below, unless the client already sent one.
+        let request_id = req
+            .headers()
+            .get("x-request-id")
+            .and_then(|id| id.to_str().ok())
+            .unwrap_or_default();
+
+        tracing::info_span!(
+            "request",
+            request_id,
+            method = %req.method(),
+            uri = %req.uri(),
+        )
+    }
+}
+
 pub async fn serve(config: Config, db: PgPool) -> anyhow::Result<()> {
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_seconds);
+    let ctx = ApiContext::new(config, db.clone());
+
+    if ctx.config.recurring_enabled {
+        recurring::spawn_scheduler(ctx.clone());
+    }
+    users::spawn_expired_tokens_cleanup(ctx.clone());
+
     // Bootstrapping an API is both more intuitive with Axum than Actix-web but also
     // a bit more confusing at the same time.
     //
@@ -91,16 +359,15 @@ pub async fn serve(config: Config, db: PgPool) -> anyhow::Result<()> {
             // rather verbose compared to Actix-web's `Data::new()`.
             //
             // It seems very logically named, but that makes it a bit annoying to type over and over.
-            .layer(Extension(ApiContext {
-                config: Arc::new(config),
-                db,
-            }))
+            .layer(Extension(ctx))
+            // Keeps an `X-Request-Id` sent by the client, e.g. a proxy in front of us,
+            // and echoes it back in the response.
             .set_x_request_id(UuidRequestId)
             .propagate_x_request_id()
             // Enables logging. Use `RUST_LOG=tower_http=debug`
             .layer(
                 TraceLayer::new_for_http()
-                    .make_span_with(DefaultMakeSpan::new().include_headers(true))
+                    .make_span_with(RequestSpan)
                     .on_response(DefaultOnResponse::new().include_headers(true)),
             ),
     );
@@ -109,19 +376,86 @@ pub async fn serve(config: Config, db: PgPool) -> anyhow::Result<()> {
     //
     // Note that any port below 1024 needs superuser privileges to bind on Linux,
     // so 80 isn't usually used as a default for that reason.
-    axum::Server::bind(&"0.0.0.0:8080".parse()?)
-        .serve(app.into_make_service())
-        .await
-        .context("error running HTTP server")
+    //
+    // On a shutdown signal, the server stops accepting connections and waits for in-flight
+    // requests to finish, so that deploys don't cut them off.
+    let draining = Arc::new(tokio::sync::Notify::new());
+    let server = axum::Server::bind(&"0.0.0.0:8080".parse()?)
+        // Handlers can see the client's address, e.g. to rate-limit logins.
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown({
+            let draining = draining.clone();
+            async move {
+                shutdown_signal().await;
+                draining.notify_one();
+            }
+        });
+
+    tokio::select! {
+        res = server => res.context("error running HTTP server")?,
+        _ = async {
+            draining.notified().await;
+            tokio::time::sleep(shutdown_timeout).await;
+        } => {
+            log::warn!(
+                "[serve] in-flight requests still running after {shutdown_timeout:?}, exiting anyway"
+            );
+        }
+    }
+
+    db.close().await;
+    log::info!("[serve] shut down");
+
+    Ok(())
+}
+
+/// Resolves on Ctrl+C, or on `SIGTERM` as sent by e.g. Kubernetes and Docker to stop a container.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log::error!("[shutdown_signal] fail to listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                log::error!("[shutdown_signal] fail to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+
+    log::info!("[shutdown_signal] shutting down, waiting for in-flight requests to finish");
 }
 
 fn api_router() -> Router {
     // This is the order that the modules were authored in.
-    Router::new().nest(
-        "/api",
-        Router::new()
-            .merge(users::router())
-            .merge(groups::router())
-            .merge(transactions::router()),
-    )
+    Router::new()
+        .nest(
+            "/api",
+            Router::new()
+                .merge(users::router())
+                .merge(groups::router())
+                .merge(transactions::router())
+                .merge(recurring::router())
+                .merge(categories::router())
+                .merge(invites::router())
+                .merge(friends::router())
+                .merge(api_keys::router()),
+        )
+        .merge(health::router())
 }
@@ -0,0 +1,513 @@
+use super::{
+    extractor::AuthUser,
+    transactions::{self, TxInsert, TxMetadata, TxType},
+    types::{Created, Timestamptz},
+    users,
+};
+use crate::{
+    commons::{to_sqlx_uuid, to_uuid},
+    dto::money::Money,
+    http::{error::Error, ApiContext, Result},
+};
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Extension, Path},
+    routing::{patch, post},
+    Json, Router,
+};
+use serde_json::{from_value as from_json_value, to_value as to_json_value};
+use sqlx::Postgres;
+
+use std::time::Duration;
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/v1/groups/:group_id/recurring",
+            post(create_recurring).get(list_recurring),
+        )
+        .route(
+            "/v1/recurring/:recurring_id",
+            patch(update_recurring).delete(delete_recurring),
+        )
+}
+
+/// A wrapper type for all requests/responses from this module.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RecurringBody<T> {
+    recurring: T,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, sqlx::Type, Copy, Clone, PartialEq)]
+#[sqlx(type_name = "recurrenceT", rename_all = "SCREAMING_SNAKE_CASE")]
+enum Period {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// A transaction from the current user to `payee_id` that is posted every `period`.
+#[derive(serde::Deserialize)]
+struct NewRecurringTx {
+    payee_id: uuid::Uuid,
+    amount: Money,
+    tx_type: TxType,
+    metadata: Option<TxMetadata>,
+    /// ISO 4217 currency code, defaults to the group's currency.
+    currency: Option<String>,
+    /// One of the group's categories, given to every transaction posted. Uncategorized if omitted.
+    category_id: Option<uuid::Uuid>,
+    period: Period,
+    /// When the first transaction is posted, defaults to now.
+    starts_at: Option<Timestamptz>,
+}
+
+#[derive(serde::Deserialize)]
+struct UpdateRecurringTx {
+    paused: bool,
+}
+
+#[derive(serde::Serialize)]
+struct RecurringTx {
+    id: uuid::Uuid,
+    group_id: uuid::Uuid,
+    payer_id: uuid::Uuid,
+    payee_id: uuid::Uuid,
+    amount: Money,
+    tx_type: TxType,
+    metadata: TxMetadata,
+    currency: String,
+    category_id: Option<uuid::Uuid>,
+    period: Period,
+    paused: bool,
+    next_run: Timestamptz,
+}
+
+/// A row of the `recurring_transactions` table as it is stored in the database.
+struct RecurringRow {
+    id: sqlx::types::Uuid,
+    group_id: sqlx::types::Uuid,
+    payer_id: sqlx::types::Uuid,
+    payee_id: sqlx::types::Uuid,
+    amount: i64,
+    tx_type: TxType,
+    metadata: serde_json::Value,
+    currency: String,
+    category_id: Option<sqlx::types::Uuid>,
+    period: Period,
+    paused: bool,
+    next_run: sqlx::types::time::OffsetDateTime,
+}
+
+impl RecurringRow {
+    fn into_recurring(self) -> Result<RecurringTx> {
+        let metadata: TxMetadata = from_json_value(self.metadata).map_err(|e| {
+            log::error!("[into_recurring] fail converting json to metadata {e:?}");
+            Error::Anyhow(anyhow!(
+                "invalid metadata stored for recurring transaction {}",
+                self.id
+            ))
+        })?;
+
+        Ok(RecurringTx {
+            id: to_uuid(self.id),
+            group_id: to_uuid(self.group_id),
+            payer_id: to_uuid(self.payer_id),
+            payee_id: to_uuid(self.payee_id),
+            amount: Money::from_minor(self.amount),
+            tx_type: self.tx_type,
+            metadata,
+            currency: self.currency,
+            category_id: self.category_id.map(to_uuid),
+            period: self.period,
+            paused: self.paused,
+            next_run: self.next_run.into(),
+        })
+    }
+}
+
+async fn create_recurring(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+    Json(req): Json<RecurringBody<NewRecurringTx>>,
+) -> Result<Created<RecurringBody<RecurringTx>>> {
+    for user_id in [auth_user.user_id, req.recurring.payee_id] {
+        if !users::is_user_in_group(ctx.clone(), Path(user_id), Path(group_id))
+            .await?
+            .0
+        {
+            log::info!("[create_recurring] user {user_id} is not in group {group_id}");
+            return Err(Error::Forbidden);
+        }
+    }
+
+    // Otherwise this would post a flipped transaction every period.
+    transactions::validate_amount(req.recurring.amount.minor())?;
+
+    // Posting would fail every time, as there is no ledger entry between a user and themselves.
+    if req.recurring.payee_id == auth_user.user_id {
+        return Err(Error::unprocessable_entity([(
+            "payee_id",
+            "cannot transact with yourself",
+        )]));
+    }
+
+    let metadata_json = to_json_value(req.recurring.metadata.unwrap_or_default()).map_err(|e| {
+        log::error!("[create_recurring] fail converting metadata to json {e:?}");
+        Error::unprocessable_entity([("metadata", "invalid metadata")])
+    })?;
+    let starts_at = req
+        .recurring
+        .starts_at
+        .map(sqlx::types::time::OffsetDateTime::from);
+
+    let mut tx = ctx.db.begin().await?;
+    let currency =
+        transactions::resolve_currency(&mut tx, group_id, req.recurring.currency.as_deref())
+            .await?;
+    let category_id =
+        transactions::validate_category_id(&mut tx, group_id, req.recurring.category_id).await?;
+
+    let row = sqlx::query_as!(
+        RecurringRow,
+        r#"
+            INSERT INTO "recurring_transactions"
+            (payer_id, payee_id, group_id, amount, tx_type, metadata, currency, category_id, period, next_run)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, coalesce($10, now()))
+            RETURNING
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                metadata, currency,
+                category_id,
+                period as "period: Period",
+                paused, next_run
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+        to_sqlx_uuid(req.recurring.payee_id),
+        to_sqlx_uuid(group_id),
+        req.recurring.amount.minor(),
+        req.recurring.tx_type as TxType,
+        metadata_json,
+        currency,
+        category_id.map(to_sqlx_uuid),
+        req.recurring.period as Period,
+        starts_at,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[create_recurring] fail to commit db transaction: {e}");
+        Error::Anyhow(anyhow!(""))
+    })?;
+
+    Ok(Created::new(RecurringBody {
+        recurring: row.into_recurring()?,
+    }))
+}
+
+async fn list_recurring(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<RecurringBody<Vec<RecurringTx>>>> {
+    if !users::is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
+        return Err(Error::Forbidden);
+    }
+
+    let recurring = sqlx::query_as!(
+        RecurringRow,
+        r#"
+            SELECT
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                metadata, currency,
+                category_id,
+                period as "period: Period",
+                paused, next_run
+            FROM "recurring_transactions"
+            WHERE group_id = $1
+            ORDER BY next_run, id
+        "#,
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(RecurringRow::into_recurring)
+    .collect::<Result<Vec<_>>>()?;
+
+    Ok(Json(RecurringBody { recurring }))
+}
+
+/// Pause or resume a recurring transaction. Only its payer may do this.
+///
+/// Occurrences that were missed while paused are skipped on resume: `next_run` moves to the first
+/// occurrence that is still in the future.
+async fn update_recurring(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(recurring_id): Path<uuid::Uuid>,
+    Json(req): Json<RecurringBody<UpdateRecurringTx>>,
+) -> Result<Json<RecurringBody<RecurringTx>>> {
+    let row = sqlx::query_as!(
+        RecurringRow,
+        r#"
+            UPDATE "recurring_transactions"
+            SET
+                paused = $1,
+                next_run = CASE
+                    WHEN paused AND NOT $1 AND next_run <= now() THEN (
+                        SELECT min(run)
+                        FROM generate_series(
+                            next_run, now() + recurrence_step(period), recurrence_step(period)
+                        ) AS run
+                        WHERE run > now()
+                    )
+                    ELSE next_run
+                END
+            WHERE id = $2 AND payer_id = $3
+            RETURNING
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                metadata, currency,
+                category_id,
+                period as "period: Period",
+                paused, next_run
+        "#,
+        req.recurring.paused,
+        to_sqlx_uuid(recurring_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Json(RecurringBody {
+            recurring: row.into_recurring()?,
+        })),
+        None => Err(not_found_or_forbidden(&ctx, recurring_id).await?),
+    }
+}
+
+/// Delete a recurring transaction. Transactions it already posted are kept.
+async fn delete_recurring(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(recurring_id): Path<uuid::Uuid>,
+) -> Result<()> {
+    let result = sqlx::query!(
+        r#"DELETE FROM "recurring_transactions" WHERE id = $1 AND payer_id = $2"#,
+        to_sqlx_uuid(recurring_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .execute(&ctx.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(not_found_or_forbidden(&ctx, recurring_id).await?);
+    }
+
+    Ok(())
+}
+
+/// Tell apart a recurring transaction that doesn't exist from one that belongs to someone else.
+async fn not_found_or_forbidden(ctx: &ApiContext, recurring_id: uuid::Uuid) -> Result<Error> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT exists(SELECT 1 FROM "recurring_transactions" WHERE id = $1) as "exists!""#,
+        to_sqlx_uuid(recurring_id),
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    Ok(if exists {
+        Error::Forbidden
+    } else {
+        Error::NotFound
+    })
+}
+
+/// Spawn the background task that posts recurring transactions when they're due.
+pub(super) fn spawn_scheduler(ctx: ApiContext) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(ctx.config.recurring_interval_seconds));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = post_due_transactions(&ctx).await {
+                log::error!("[recurring_scheduler] fail to post due transactions: {e:?}");
+            }
+        }
+    });
+}
+
+/// Post every recurring transaction that is due, one occurrence at a time.
+///
+/// Each occurrence is posted and its `next_run` advanced in a single database transaction,
+/// so that a crash in between can't post it twice. A template that is far behind (e.g. after
+/// the API was down for a while) is picked up again until it has caught up.
+async fn post_due_transactions(ctx: &ApiContext) -> Result<()> {
+    loop {
+        let mut tx = ctx.db.begin().await?;
+
+        // `SKIP LOCKED` lets multiple instances of the API run the scheduler at the same time.
+        let row = sqlx::query_as!(
+            RecurringRow,
+            r#"
+                SELECT
+                    r.id, r.group_id, r.payer_id, r.payee_id, r.amount,
+                    r.tx_type as "tx_type: TxType",
+                    r.metadata, r.currency,
+                    r.category_id,
+                    r.period as "period: Period",
+                    r.paused, r.next_run
+                FROM "recurring_transactions" r
+                WHERE NOT r.paused AND r.next_run <= now()
+                ORDER BY r.next_run
+                LIMIT 1
+                FOR UPDATE OF r SKIP LOCKED
+            "#,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(());
+        };
+        let recurring_id = to_uuid(row.id);
+
+        match post_occurrence(ctx, &mut tx, row).await {
+            Ok(()) => {}
+            // The template itself can't be posted, e.g. because the payee has left the group.
+            // Pause it so it isn't retried on every run. Its payer can resume it once the
+            // problem is fixed.
+            Err(e @ (Error::UnprocessableEntity { .. } | Error::Forbidden)) => {
+                log::error!("[post_due_transactions] fail to post recurring transaction {recurring_id}, pausing it: {e:?}");
+                tx.rollback().await?;
+
+                sqlx::query!(
+                    r#"UPDATE "recurring_transactions" SET paused = true WHERE id = $1"#,
+                    to_sqlx_uuid(recurring_id),
+                )
+                .execute(&ctx.db)
+                .await?;
+                continue;
+            }
+            // Anything else, e.g. the database being unavailable, isn't the template's fault.
+            // Leave it due so the next run tries again.
+            Err(e) => {
+                tx.rollback().await?;
+                return Err(e);
+            }
+        }
+
+        tx.commit().await.map_err(|e| {
+            log::error!("[post_due_transactions] fail to commit db transaction: {e}");
+            Error::Anyhow(anyhow!(""))
+        })?;
+        log::info!("[post_due_transactions] posted recurring transaction {recurring_id}");
+    }
+}
+
+/// Post a single occurrence of a recurring transaction and schedule the next one.
+async fn post_occurrence(
+    ctx: &ApiContext,
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    row: RecurringRow,
+) -> Result<()> {
+    let recurring = row.into_recurring()?;
+
+    transactions::insert_transaction(
+        ctx,
+        tx,
+        TxInsert {
+            group_id: recurring.group_id,
+            payer_id: recurring.payer_id,
+            payee_id: recurring.payee_id,
+            amount: recurring.amount.minor(),
+            tx_type: recurring.tx_type,
+            description: None,
+            metadata: &recurring.metadata,
+            currency: &recurring.currency,
+            category_id: recurring.category_id,
+            is_settlement: false,
+        },
+    )
+    .await?;
+
+    sqlx::query!(
+        r#"
+            UPDATE "recurring_transactions"
+            SET next_run = next_run + recurrence_step(period)
+            WHERE id = $1
+        "#,
+        to_sqlx_uuid(recurring.id),
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn posted_transactions_keep_the_template_category(db: sqlx::PgPool) {
+        let ctx = ApiContext::for_tests(db);
+        let (_, alice) = ctx.sign_up("alice").await;
+        let (bob_id, _) = ctx.sign_up("bob").await;
+        let group_id = ctx.create_group(&alice, &[bob_id]).await;
+
+        let (_, body) = ctx
+            .request(
+                http::Method::POST,
+                &format!("/api/v1/groups/{group_id}/categories"),
+                Some(&alice),
+                Some(serde_json::json!({ "category": { "name": "Rent" } })),
+            )
+            .await;
+        let rent_id = body["category"]["id"].clone();
+
+        let (status, body) = ctx
+            .request(
+                http::Method::POST,
+                &format!("/api/v1/groups/{group_id}/recurring"),
+                Some(&alice),
+                Some(serde_json::json!({
+                    "recurring": {
+                        "payee_id": bob_id,
+                        "amount": "500.00",
+                        "tx_type": "Credit",
+                        "category_id": rent_id,
+                        "period": "Monthly",
+                    }
+                })),
+            )
+            .await;
+        assert_eq!(status, http::StatusCode::CREATED);
+        assert_eq!(body["recurring"]["category_id"], rent_id);
+
+        // Due right away, as it has no `starts_at`.
+        post_due_transactions(&ctx).await.unwrap();
+
+        let category_ids = sqlx::query_scalar!(r#"SELECT category_id FROM "transactions""#)
+            .fetch_all(&ctx.db)
+            .await
+            .unwrap();
+        assert_eq!(
+            category_ids,
+            [Some(to_sqlx_uuid(
+                rent_id.as_str().unwrap().parse().unwrap()
+            ))]
+        );
+    }
+}
@@ -1,26 +1,78 @@
-use super::{extractor::AuthUser, users};
+use super::{
+    categories::UNCATEGORIZED,
+    extractor::{AuthUser, IdempotencyKey},
+    types::{Created, Timestamptz},
+    users,
+};
 use crate::{
     commons::{to_sqlx_uuid, to_uuid},
+    dto::{ledger::SettlementBody, money::Money},
     http::{
         error::{Error, ResultExt},
-        ApiContext, Result,
+        ApiContext, Pagination, Result,
     },
+    logic::{currency, group::GroupsHandler, ledger::LedgerUpdate, split},
 };
 
 use anyhow::anyhow;
 use axum::{
-    extract::{Extension, Path},
-    routing::post,
+    extract::{Extension, Path, Query},
+    routing::{get, post},
     Json, Router,
 };
-use serde_json::to_value as to_json_value;
+use serde_json::{from_value as from_json_value, to_value as to_json_value};
+use sqlx::Postgres;
 
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
 
 pub fn router() -> Router {
-    Router::new().route("/v1/transactions", post(create_transaction))
+    Router::new()
+        .route(
+            "/v1/transactions",
+            post(create_transaction).get(list_transactions_by_group),
+        )
+        .route("/v1/transactions/batch", post(create_transactions_batch))
+        .route(
+            "/v1/transactions/:tx_id",
+            get(find_transaction_by_id)
+                .put(update_transaction)
+                .delete(delete_transaction),
+        )
+        .route("/v1/transactions/:tx_id/reverse", post(reverse_transaction))
+        .route(
+            "/v1/transactions/:tx_id/ack",
+            post(ack_transaction).patch(ack_transaction),
+        )
+        .route(
+            "/v1/users/:user_id/transactions",
+            get(get_transactions_by_user),
+        )
+        .route(
+            "/v1/groups/:group_id/transactions",
+            get(get_group_transactions),
+        )
+        .route("/v1/groups/:group_id/expenses", post(create_expense))
+        .route(
+            "/v1/groups/:group_id/settle/record",
+            post(record_settlement),
+        )
+        .route("/v1/me/activity", get(get_activity))
 }
 
+/// How long an `Idempotency-Key` is remembered for, see `create_transaction`.
+const IDEMPOTENCY_KEY_TTL_HOURS: i32 = 24;
+
+/// Longer transaction descriptions are rejected, as they're meant to be a short note.
+const MAX_DESCRIPTION_LENGTH: usize = 280;
+
+/// Upper bound on the number of transactions created in a single batch, so that one request
+/// can't hold a database transaction open for too long.
+const MAX_TX_BATCH_SIZE: usize = 200;
+
 /// A wrapper type for all requests/responses from this module.
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct TxBody<T> {
@@ -34,6 +86,21 @@ pub enum TxType {
     Debit,
 }
 
+impl FromStr for TxType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "credit" => Ok(TxType::Credit),
+            "debit" => Ok(TxType::Debit),
+            _ => Err(Error::unprocessable_entity([(
+                "tx_type",
+                "unknown transaction type",
+            )])),
+        }
+    }
+}
+
 impl Display for TxType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -47,7 +114,7 @@ impl Display for TxType {
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[derive(serde::Serialize, serde::Deserialize, sqlx::Type, Copy, Clone, PartialEq)]
 #[sqlx(type_name = "ackT", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum AckStatus {
     NotAck,
@@ -67,13 +134,94 @@ impl Display for AckStatus {
     }
 }
 
-type TxMetadata = HashMap<String, String>;
+pub(super) type TxMetadata = HashMap<String, String>;
 #[derive(serde::Deserialize)]
 struct NewTx {
     group_id: uuid::Uuid,
     payee_id: uuid::Uuid,
-    amount: i64,
+    amount: Money,
     tx_type: TxType,
+    /// What the transaction was for, see `MAX_DESCRIPTION_LENGTH`.
+    description: Option<String>,
+    metadata: Option<TxMetadata>,
+    /// ISO 4217 currency code, defaults to the group's currency.
+    currency: Option<String>,
+    /// One of the group's categories, uncategorized if omitted.
+    category_id: Option<uuid::Uuid>,
+}
+
+/// A wrapper type for expense requests.
+#[derive(serde::Deserialize)]
+struct ExpenseBody<T> {
+    expense: T,
+}
+
+/// An expense paid by `payer_id` on behalf of the group.
+///
+/// Without `splits`, the expense is split equally between all members of the group.
+#[derive(serde::Deserialize)]
+struct NewExpense {
+    payer_id: uuid::Uuid,
+    amount: Money,
+    /// What the expense was for, given to each of the transactions it's split into.
+    description: Option<String>,
+    metadata: Option<TxMetadata>,
+    /// ISO 4217 currency code, defaults to the group's currency.
+    currency: Option<String>,
+    /// One of the group's categories, uncategorized if omitted.
+    category_id: Option<uuid::Uuid>,
+    splits: Option<Splits>,
+}
+
+/// A payment the current user made to `to_user` to settle up what they owed.
+#[derive(serde::Deserialize)]
+struct NewSettlement {
+    to_user: uuid::Uuid,
+    amount: Money,
+    /// ISO 4217 currency code, defaults to the group's currency.
+    currency: Option<String>,
+}
+
+/// How an expense is split between its participants.
+///
+/// The mode is inferred from the fields of the entries, e.g.
+/// `[{ "user_id": ..., "percentage": 40 }, ...]`, `[{ "user_id": ..., "shares": 3 }, ...]`
+/// or `[{ "user_id": ..., "amount": "12.50" }, ...]`.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Splits {
+    Percentage(Vec<PercentageSplit>),
+    Shares(Vec<ShareSplit>),
+    Exact(Vec<ExactSplit>),
+}
+
+/// The percentage of an expense that `user_id` is responsible for.
+#[derive(serde::Deserialize)]
+struct PercentageSplit {
+    user_id: uuid::Uuid,
+    percentage: i64,
+}
+
+/// The number of shares of an expense that `user_id` is responsible for.
+#[derive(serde::Deserialize)]
+struct ShareSplit {
+    user_id: uuid::Uuid,
+    shares: i64,
+}
+
+/// The exact amount of an expense that `user_id` is responsible for.
+#[derive(serde::Deserialize)]
+struct ExactSplit {
+    user_id: uuid::Uuid,
+    amount: Money,
+}
+
+#[derive(serde::Deserialize, Default, PartialEq)]
+#[serde(default)]
+struct UpdateTx {
+    amount: Option<Money>,
+    /// An empty description clears it.
+    description: Option<String>,
     metadata: Option<TxMetadata>,
 }
 
@@ -83,17 +231,181 @@ pub struct Transaction {
     pub group_id: uuid::Uuid,
     pub payer_id: uuid::Uuid,
     pub payee_id: uuid::Uuid,
-    pub amount: i64,
+    pub amount: Money,
     pub tx_type: TxType,
     pub ack_status: AckStatus,
+    pub description: Option<String>,
     pub metadata: TxMetadata,
+    pub currency: String,
+    pub category_id: Option<uuid::Uuid>,
+    /// Whether this is a payment to settle up rather than an expense, see `record_settlement`.
+    pub is_settlement: bool,
+    /// The transaction this one reverses, see `reverse_transaction`.
+    pub reverses_tx_id: Option<uuid::Uuid>,
+    pub created_at: Timestamptz,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ActivityBody {
+    activity: Vec<ActivityEntry>,
+}
+
+/// A transaction the current user is part of, with the names a client needs to show it in a
+/// feed without looking up the group and the other user separately.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ActivityEntry {
+    pub id: uuid::Uuid,
+    pub group_id: uuid::Uuid,
+    pub group_name: String,
+    /// The other side of the transaction from the current user's point of view.
+    pub counterparty_id: uuid::Uuid,
+    pub counterparty_username: String,
+    pub amount: Money,
+    pub currency: String,
+    pub tx_type: TxType,
+    pub description: Option<String>,
+    pub is_settlement: bool,
+    pub created_at: Timestamptz,
+}
+
+/// A row of the activity feed query, see `get_activity`.
+struct ActivityRow {
+    id: sqlx::types::Uuid,
+    group_id: sqlx::types::Uuid,
+    group_name: String,
+    counterparty_id: sqlx::types::Uuid,
+    counterparty_username: String,
+    amount: i64,
+    currency: String,
+    tx_type: TxType,
+    description: Option<String>,
+    is_settlement: bool,
+    created_at: sqlx::types::time::OffsetDateTime,
+}
+
+/// Filters for listing a group's transactions, combined with AND.
+///
+/// Kept as strings so that invalid values can be reported per field.
+#[derive(serde::Deserialize)]
+struct ListTxQuery {
+    group_id: uuid::Uuid,
+    tx_type: Option<String>,
+    /// RFC 3339 timestamp, only list transactions created at or after it.
+    from: Option<String>,
+    /// RFC 3339 timestamp, only list transactions created at or before it.
+    to: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UserTxQuery {
+    tx_type: Option<TxType>,
 }
 
+#[derive(serde::Deserialize)]
+struct GroupTxQuery {
+    /// The name of one of the group's categories, or `UNCATEGORIZED`.
+    category: Option<String>,
+    /// Only list transactions between the current user and this member, in either direction.
+    with: Option<uuid::Uuid>,
+}
+
+/// A row of the `transactions` table as it is stored in the database.
+///
+/// Debit amounts are stored negated (see `create_transaction`), so use
+/// `into_transaction()` to get back the `Transaction` a client would have sent.
+struct TxRow {
+    id: sqlx::types::Uuid,
+    group_id: sqlx::types::Uuid,
+    payer_id: sqlx::types::Uuid,
+    payee_id: sqlx::types::Uuid,
+    amount: i64,
+    tx_type: TxType,
+    ack_status: AckStatus,
+    metadata: serde_json::Value,
+    currency: String,
+    category_id: Option<sqlx::types::Uuid>,
+    reverses_tx_id: Option<sqlx::types::Uuid>,
+    description: Option<String>,
+    is_settlement: bool,
+    created_at: sqlx::types::time::OffsetDateTime,
+}
+
+impl TxRow {
+    fn into_transaction(self) -> Result<Transaction> {
+        let metadata: TxMetadata = from_json_value(self.metadata).map_err(|e| {
+            log::error!("[into_transaction] fail converting json to metadata {e:?}");
+            Error::Anyhow(anyhow!(
+                "invalid metadata stored for transaction {}",
+                self.id
+            ))
+        })?;
+
+        let amount = if TxType::Debit == self.tx_type {
+            -self.amount
+        } else {
+            self.amount
+        };
+
+        Ok(Transaction {
+            id: to_uuid(self.id),
+            group_id: to_uuid(self.group_id),
+            payer_id: to_uuid(self.payer_id),
+            payee_id: to_uuid(self.payee_id),
+            amount: Money::from_minor(amount),
+            tx_type: self.tx_type,
+            ack_status: self.ack_status,
+            description: self.description,
+            metadata,
+            currency: self.currency,
+            category_id: self.category_id.map(to_uuid),
+            is_settlement: self.is_settlement,
+            reverses_tx_id: self.reverses_tx_id.map(to_uuid),
+            created_at: self.created_at.into(),
+        })
+    }
+}
+
+/// Record a transaction from the current user to `payee_id`.
+///
+/// Clients on flaky networks should send an `Idempotency-Key` header that's unique per
+/// transaction: retrying with the same key within `IDEMPOTENCY_KEY_TTL_HOURS` returns the
+/// transaction that was created the first time instead of creating it again.
 async fn create_transaction(
     ctx: Extension<ApiContext>,
     auth_user: AuthUser,
+    idempotency_key: IdempotencyKey,
     Json(req): Json<TxBody<NewTx>>,
-) -> Result<Json<TxBody<Transaction>>> {
+) -> Result<Created<TxBody<Transaction>>> {
+    if let Some(key) = &idempotency_key.0 {
+        // Expired keys are cleaned up whenever their user sends a new one, rather than by a
+        // background task.
+        sqlx::query!(
+            r#"DELETE FROM "idempotency_keys" WHERE user_id = $1 AND expires_at <= now()"#,
+            to_sqlx_uuid(auth_user.user_id),
+        )
+        .execute(&ctx.db)
+        .await?;
+
+        if let Some(transaction) = find_idempotent_transaction(&ctx, auth_user.user_id, key).await?
+        {
+            log::info!(
+                "[create_transaction] replaying transaction {} for idempotency key",
+                transaction.id
+            );
+            return Ok(created_transaction(transaction));
+        }
+    }
+
+    validate_amount(req.transaction.amount.minor())?;
+
+    // There is no ledger entry between a user and themselves.
+    if req.transaction.payee_id == auth_user.user_id {
+        return Err(Error::unprocessable_entity([(
+            "payee_id",
+            "cannot transact with yourself",
+        )]));
+    }
+
     // check if both auth_user and payee_id are in the group
     if !users::is_user_in_group(
         ctx.clone(),
@@ -126,139 +438,1824 @@ async fn create_transaction(
         return Err(Error::Forbidden);
     }
 
-    // Prepare metadata and amount
-    let req_metadata = req.transaction.metadata.unwrap_or_default();
-    let metadata_json = to_json_value(req_metadata.clone()).map_err(|e| {
-        log::error!("[create_transaction] fail converting metadata to json {e:?}");
+    let metadata = req.transaction.metadata.unwrap_or_default();
+    let description = parse_description(req.transaction.description.as_deref())?;
+
+    // Do db operations
+    let mut tx = ctx.db.begin().await?;
+    let currency = resolve_currency(
+        &mut tx,
+        req.transaction.group_id,
+        req.transaction.currency.as_deref(),
+    )
+    .await?;
+    let category_id = validate_category_id(
+        &mut tx,
+        req.transaction.group_id,
+        req.transaction.category_id,
+    )
+    .await?;
+
+    let transaction = insert_transaction(
+        &ctx,
+        &mut tx,
+        TxInsert {
+            group_id: req.transaction.group_id,
+            payer_id: auth_user.user_id,
+            payee_id: req.transaction.payee_id,
+            amount: req.transaction.amount.minor(),
+            tx_type: req.transaction.tx_type,
+            description: description.as_deref(),
+            metadata: &metadata,
+            currency: &currency,
+            category_id,
+            is_settlement: false,
+        },
+    )
+    .await?;
+
+    if let Some(key) = &idempotency_key.0 {
+        // If a concurrent request with the same key got here first, this waits for it to
+        // commit and then leaves its key alone, so that its transaction is the only one kept.
+        let inserted = sqlx::query!(
+            r#"
+                INSERT INTO "idempotency_keys" (user_id, key, transaction_id, expires_at)
+                VALUES ($1, $2, $3, now() + make_interval(hours => $4))
+                ON CONFLICT (user_id, key) DO UPDATE
+                SET transaction_id = excluded.transaction_id, expires_at = excluded.expires_at
+                WHERE "idempotency_keys".expires_at <= now()
+            "#,
+            to_sqlx_uuid(auth_user.user_id),
+            key,
+            to_sqlx_uuid(transaction.id),
+            IDEMPOTENCY_KEY_TTL_HOURS,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if inserted == 0 {
+            tx.rollback().await?;
+
+            let transaction = find_idempotent_transaction(&ctx, auth_user.user_id, key)
+                .await?
+                .ok_or_else(|| anyhow!("idempotency key {key:?} vanished during a retry"))?;
+            return Ok(created_transaction(transaction));
+        }
+    }
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[create_transaction] fail to commit db transaction: {e}");
+        Error::Anyhow(anyhow!(""))
+    })?;
+
+    Ok(created_transaction(transaction))
+}
+
+fn created_transaction(transaction: Transaction) -> Created<TxBody<Transaction>> {
+    let location = format!("/api/v1/transactions/{}", transaction.id);
+
+    Created::new(TxBody { transaction }).at(location)
+}
+
+/// The transaction that `user_id` created with `key` as their `Idempotency-Key`, unless it expired.
+async fn find_idempotent_transaction(
+    ctx: &ApiContext,
+    user_id: uuid::Uuid,
+    key: &str,
+) -> Result<Option<Transaction>> {
+    sqlx::query_as!(
+        TxRow,
+        r#"
+            SELECT
+                t.id, t.group_id, t.payer_id, t.payee_id, t.amount,
+                t.tx_type as "tx_type: TxType",
+                t.ack_status as "ack_status: AckStatus",
+                t.metadata, t.currency,
+                t.category_id,
+                t.reverses_tx_id,
+                t.description,
+                t.is_settlement,
+                t.created_at
+            FROM "idempotency_keys" k
+            INNER JOIN "transactions" t ON t.id = k.transaction_id
+            WHERE k.user_id = $1 AND k.key = $2 AND k.expires_at > now()
+        "#,
+        to_sqlx_uuid(user_id),
+        key,
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .map(TxRow::into_transaction)
+    .transpose()
+}
+
+/// Create many transactions at once, e.g. when importing historical expenses.
+///
+/// Every entry is validated up front, then all of them are recorded in a single database
+/// transaction, so either all of them are created or none are.
+async fn create_transactions_batch(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Json(req): Json<TxBody<Vec<NewTx>>>,
+) -> Result<Json<TxBody<Vec<Transaction>>>> {
+    if req.transaction.is_empty() {
+        return Err(Error::unprocessable_entity([(
+            "transaction",
+            "must not be empty",
+        )]));
+    }
+    if req.transaction.len() > MAX_TX_BATCH_SIZE {
+        return Err(Error::unprocessable_entity([(
+            "transaction",
+            format!("at most {MAX_TX_BATCH_SIZE} transactions per batch"),
+        )]));
+    }
+
+    // Entries tend to repeat the same groups and payees, so only check each membership once.
+    let mut members: HashSet<(uuid::Uuid, uuid::Uuid)> = HashSet::new();
+    for new_tx in &req.transaction {
+        validate_amount(new_tx.amount.minor())?;
+        if new_tx.payee_id == auth_user.user_id {
+            return Err(Error::unprocessable_entity([(
+                "payee_id",
+                "cannot transact with yourself",
+            )]));
+        }
+
+        for user_id in [auth_user.user_id, new_tx.payee_id] {
+            if members.contains(&(user_id, new_tx.group_id)) {
+                continue;
+            }
+            if !users::is_user_in_group(ctx.clone(), Path(user_id), Path(new_tx.group_id))
+                .await?
+                .0
+            {
+                log::info!(
+                    "[create_transactions_batch] user {} is not in group {}",
+                    user_id,
+                    new_tx.group_id,
+                );
+                return Err(Error::Forbidden);
+            }
+            members.insert((user_id, new_tx.group_id));
+        }
+    }
+
+    let mut tx = ctx.db.begin().await?;
+
+    let mut transactions = Vec::with_capacity(req.transaction.len());
+    for new_tx in req.transaction {
+        let currency =
+            resolve_currency(&mut tx, new_tx.group_id, new_tx.currency.as_deref()).await?;
+        let category_id =
+            validate_category_id(&mut tx, new_tx.group_id, new_tx.category_id).await?;
+        let description = parse_description(new_tx.description.as_deref())?;
+
+        transactions.push(
+            insert_transaction(
+                &ctx,
+                &mut tx,
+                TxInsert {
+                    group_id: new_tx.group_id,
+                    payer_id: auth_user.user_id,
+                    payee_id: new_tx.payee_id,
+                    amount: new_tx.amount.minor(),
+                    tx_type: new_tx.tx_type,
+                    description: description.as_deref(),
+                    metadata: &new_tx.metadata.unwrap_or_default(),
+                    currency: &currency,
+                    category_id,
+                    is_settlement: false,
+                },
+            )
+            .await?,
+        );
+    }
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[create_transactions_batch] fail to commit db transaction: {e}");
+        Error::Anyhow(anyhow!(""))
+    })?;
+
+    Ok(Json(TxBody {
+        transaction: transactions,
+    }))
+}
+
+/// Split an expense between members of a group, either equally, by percentage, by shares
+/// or by exact amounts.
+///
+/// For every participant other than the payer, this records a `Debit` transaction from that
+/// participant to the payer for their share, i.e. the participant now owes the payer that share.
+async fn create_expense(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+    Json(req): Json<ExpenseBody<NewExpense>>,
+) -> Result<Json<TxBody<Vec<Transaction>>>> {
+    validate_amount(req.expense.amount.minor())?;
+
+    for user_id in [auth_user.user_id, req.expense.payer_id] {
+        if !users::is_user_in_group(ctx.clone(), Path(user_id), Path(group_id))
+            .await?
+            .0
+        {
+            log::info!("[create_expense] user {user_id} is not in group {group_id}");
+            return Err(Error::Forbidden);
+        }
+    }
+
+    let metadata = req.expense.metadata.unwrap_or_default();
+    let payer_id = req.expense.payer_id;
+    let description = parse_description(req.expense.description.as_deref())?;
+
+    let mut tx = ctx.db.begin().await?;
+    let currency = resolve_currency(&mut tx, group_id, req.expense.currency.as_deref()).await?;
+    let category_id = validate_category_id(&mut tx, group_id, req.expense.category_id).await?;
+
+    // Sort the members so the remainder of the split always goes to the same members.
+    let mut member_ids = ctx
+        .groups
+        .get_users_by_group(&group_id, None, Some(&mut tx))
+        .await?
+        .into_iter()
+        .map(|u| u.id)
+        .collect::<Vec<_>>();
+    member_ids.sort();
+
+    let shares = split_expense(req.expense.amount.minor(), req.expense.splits, member_ids)?;
+
+    let mut transactions = Vec::with_capacity(shares.len());
+    for (member_id, share) in shares {
+        if member_id == payer_id || share == 0 {
+            continue;
+        }
+
+        transactions.push(
+            insert_transaction(
+                &ctx,
+                &mut tx,
+                TxInsert {
+                    group_id,
+                    payer_id: member_id,
+                    payee_id: payer_id,
+                    amount: share,
+                    tx_type: TxType::Debit,
+                    description: description.as_deref(),
+                    metadata: &metadata,
+                    currency: &currency,
+                    category_id,
+                    is_settlement: false,
+                },
+            )
+            .await?,
+        );
+    }
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[create_expense] fail to commit db transaction: {e}");
+        Error::Anyhow(anyhow!(""))
+    })?;
+
+    Ok(Json(TxBody {
+        transaction: transactions,
+    }))
+}
+
+/// Record that the current user paid `to_user` back some or all of what they owe them.
+///
+/// This is recorded as a `Credit` from the current user to `to_user` with `is_settlement` set,
+/// which moves both sides of their ledger towards zero like any other transaction. Paying more
+/// than is owed in the currency is rejected, as that would leave `to_user` owing instead.
+async fn record_settlement(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+    Json(req): Json<SettlementBody<NewSettlement>>,
+) -> Result<Created<TxBody<Transaction>>> {
+    let amount = req.settlement.amount.minor();
+    validate_amount(amount)?;
+    if req.settlement.to_user == auth_user.user_id {
+        return Err(Error::unprocessable_entity([(
+            "to_user",
+            "cannot settle with yourself",
+        )]));
+    }
+
+    for user_id in [auth_user.user_id, req.settlement.to_user] {
+        if !users::is_user_in_group(ctx.clone(), Path(user_id), Path(group_id))
+            .await?
+            .0
+        {
+            log::info!("[record_settlement] user {user_id} is not in group {group_id}");
+            return Err(Error::Forbidden);
+        }
+    }
+
+    let mut tx = ctx.db.begin().await?;
+    let currency = resolve_currency(&mut tx, group_id, req.settlement.currency.as_deref()).await?;
+
+    // Locked so that a concurrent settlement can't pay the same debt off twice.
+    let owed = ctx
+        .ledger
+        .lock_ledger_amounts(group_id, auth_user.user_id, &mut tx)
+        .await?
+        .into_iter()
+        .find(|e| e.other_user == req.settlement.to_user && e.currency == currency)
+        .map_or(0, |e| -e.amount);
+    if owed <= 0 {
+        return Err(Error::unprocessable_entity([(
+            "to_user",
+            "nothing is owed to this user in this currency",
+        )]));
+    }
+    if amount > owed {
+        return Err(Error::unprocessable_entity([(
+            "amount",
+            "is more than is owed",
+        )]));
+    }
+
+    let transaction = insert_transaction(
+        &ctx,
+        &mut tx,
+        TxInsert {
+            group_id,
+            payer_id: auth_user.user_id,
+            payee_id: req.settlement.to_user,
+            amount,
+            tx_type: TxType::Credit,
+            description: None,
+            metadata: &TxMetadata::default(),
+            currency: &currency,
+            category_id: None,
+            is_settlement: true,
+        },
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[record_settlement] fail to commit db transaction: {e}");
+        Error::Anyhow(anyhow!(""))
+    })?;
+
+    log::info!(
+        "[record_settlement] user {} paid {amount} {currency} to {} in group {group_id}",
+        auth_user.user_id,
+        req.settlement.to_user,
+    );
+
+    Ok(created_transaction(transaction))
+}
+
+/// Compute how much of `amount` each participant of an expense is responsible for.
+///
+/// Without `splits`, every member of the group takes part equally.
+fn split_expense(
+    amount: i64,
+    splits: Option<Splits>,
+    member_ids: Vec<uuid::Uuid>,
+) -> Result<Vec<(uuid::Uuid, i64)>> {
+    match splits {
+        None => {
+            let shares = split::equal_split(amount, member_ids.len());
+            Ok(member_ids.into_iter().zip(shares).collect())
+        }
+        Some(Splits::Percentage(splits)) => {
+            validate_split_users(splits.iter().map(|s| s.user_id), &member_ids)?;
+            if splits.iter().any(|s| s.percentage <= 0) {
+                return Err(Error::unprocessable_entity([(
+                    "splits",
+                    "percentages must be positive",
+                )]));
+            }
+            if splits.iter().map(|s| s.percentage).sum::<i64>() != 100 {
+                return Err(Error::unprocessable_entity([(
+                    "splits",
+                    "percentages must sum to 100",
+                )]));
+            }
+
+            // Leftover units from rounding go to participants in the order they were listed.
+            let percentages = splits.iter().map(|s| s.percentage).collect::<Vec<_>>();
+            let order = (0..splits.len()).collect::<Vec<_>>();
+            let shares = split::weighted_split(amount, &percentages, &order);
+            Ok(splits.iter().map(|s| s.user_id).zip(shares).collect())
+        }
+        Some(Splits::Shares(splits)) => {
+            validate_split_users(splits.iter().map(|s| s.user_id), &member_ids)?;
+            if splits.iter().any(|s| s.shares <= 0) {
+                return Err(Error::unprocessable_entity([(
+                    "splits",
+                    "shares must be positive",
+                )]));
+            }
+
+            // Leftover units from rounding go to the participants with the most shares first,
+            // ties are broken by the order they were listed.
+            let weights = splits.iter().map(|s| s.shares).collect::<Vec<_>>();
+            let mut order = (0..splits.len()).collect::<Vec<_>>();
+            order.sort_by_key(|i| std::cmp::Reverse(weights[*i]));
+            let shares = split::weighted_split(amount, &weights, &order);
+            Ok(splits.iter().map(|s| s.user_id).zip(shares).collect())
+        }
+        Some(Splits::Exact(splits)) => {
+            validate_split_users(splits.iter().map(|s| s.user_id), &member_ids)?;
+            if splits.iter().any(|s| s.amount.minor() <= 0) {
+                return Err(Error::unprocessable_entity([(
+                    "splits",
+                    "amounts must be positive",
+                )]));
+            }
+            let total = splits
+                .iter()
+                .try_fold(0i64, |total, s| total.checked_add(s.amount.minor()));
+            if total != Some(amount) {
+                return Err(Error::unprocessable_entity([(
+                    "splits",
+                    "must sum to total",
+                )]));
+            }
+
+            Ok(splits
+                .iter()
+                .map(|s| (s.user_id, s.amount.minor()))
+                .collect())
+        }
+    }
+}
+
+/// Check that every user taking part in a split is a member of the group, and appears only once.
+fn validate_split_users(
+    user_ids: impl Iterator<Item = uuid::Uuid>,
+    member_ids: &[uuid::Uuid],
+) -> Result<()> {
+    let mut seen = HashSet::new();
+    for user_id in user_ids {
+        if !member_ids.contains(&user_id) {
+            return Err(Error::unprocessable_entity([(
+                "splits",
+                "user is not in group",
+            )]));
+        }
+        if !seen.insert(user_id) {
+            return Err(Error::unprocessable_entity([("splits", "duplicate user")]));
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse an optional RFC 3339 timestamp from the query parameter `field`.
+fn parse_timestamp(
+    field: &'static str,
+    value: Option<&str>,
+) -> Result<Option<sqlx::types::time::OffsetDateTime>> {
+    value
+        .map(|v| {
+            Timestamptz::from_str(v).map(Into::into).map_err(|_| {
+                Error::unprocessable_entity([(field, "must be an RFC 3339 timestamp")])
+            })
+        })
+        .transpose()
+}
+
+/// Check that an amount in minor units is positive. `tx_type` alone determines the direction of
+/// a transaction, so a negative amount would silently flip it.
+pub(super) fn validate_amount(amount: i64) -> Result<()> {
+    if amount <= 0 {
+        return Err(Error::unprocessable_entity([(
+            "amount",
+            "must be positive",
+        )]));
+    }
+
+    Ok(())
+}
+
+/// Validate a new transaction's description, treating a blank one as none at all.
+pub(super) fn parse_description(description: Option<&str>) -> Result<Option<String>> {
+    Ok(description
+        .map(validate_description)
+        .transpose()?
+        .filter(|description| !description.is_empty()))
+}
+
+/// Trim `description` and check it isn't longer than `MAX_DESCRIPTION_LENGTH`.
+fn validate_description(description: &str) -> Result<String> {
+    let description = description.trim();
+    if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+        return Err(Error::unprocessable_entity([(
+            "description",
+            format!("must be at most {MAX_DESCRIPTION_LENGTH} characters"),
+        )]));
+    }
+
+    Ok(description.to_string())
+}
+
+/// Validate the currency a client asked for, or fall back to the currency of the group.
+pub(super) async fn resolve_currency(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    group_id: uuid::Uuid,
+    requested: Option<&str>,
+) -> Result<String> {
+    if let Some(code) = requested {
+        return currency::validate_currency(code);
+    }
+
+    sqlx::query_scalar!(
+        r#"SELECT currency FROM "groups" WHERE id = $1"#,
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or(Error::NotFound)
+}
+
+/// Check that a category a client referenced belongs to the group of the transaction.
+pub(super) async fn validate_category_id(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    group_id: uuid::Uuid,
+    category_id: Option<uuid::Uuid>,
+) -> Result<Option<uuid::Uuid>> {
+    let Some(category_id) = category_id else {
+        return Ok(None);
+    };
+
+    let belongs = sqlx::query_scalar!(
+        r#"SELECT exists(SELECT 1 FROM "categories" WHERE id = $1 AND group_id = $2) as "exists!""#,
+        to_sqlx_uuid(category_id),
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    if !belongs {
+        return Err(Error::unprocessable_entity([(
+            "category",
+            "does not belong to group",
+        )]));
+    }
+
+    Ok(Some(category_id))
+}
+
+/// A transaction to record with `insert_transaction`.
+///
+/// `amount` is the amount as the client sees it; it is negated for `Debit` before storing.
+pub(super) struct TxInsert<'a> {
+    pub group_id: uuid::Uuid,
+    pub payer_id: uuid::Uuid,
+    pub payee_id: uuid::Uuid,
+    pub amount: i64,
+    pub tx_type: TxType,
+    pub description: Option<&'a str>,
+    pub metadata: &'a TxMetadata,
+    pub currency: &'a str,
+    pub category_id: Option<uuid::Uuid>,
+    pub is_settlement: bool,
+}
+
+/// Record a transaction and apply it to the pairwise ledger of its payer and payee,
+/// within the given database transaction.
+pub(super) async fn insert_transaction(
+    ctx: &ApiContext,
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    new_tx: TxInsert<'_>,
+) -> Result<Transaction> {
+    let TxInsert {
+        group_id,
+        payer_id,
+        payee_id,
+        amount,
+        tx_type,
+        description,
+        metadata,
+        currency,
+        category_id,
+        is_settlement,
+    } = new_tx;
+
+    let metadata_json = to_json_value(metadata).map_err(|e| {
+        log::error!("[insert_transaction] fail converting metadata to json {e:?}");
         Error::unprocessable_entity([("metadata", "invalid metadata")])
     })?;
 
-    let amount = if TxType::Debit == req.transaction.tx_type {
-        -req.transaction.amount
+    let amount = if TxType::Debit == tx_type {
+        -amount
     } else {
-        req.transaction.amount
+        amount
     };
 
-    // Do db operations
-    let mut tx = ctx.db.begin().await?;
-    let txn_id = sqlx::query_scalar!(
+    let row = sqlx::query_as!(
+        TxRow,
         r#"
             INSERT INTO "transactions"
-            (payer_id, payee_id, group_id, amount, tx_type, ack_status, metadata)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            RETURNING id
+            (payer_id, payee_id, group_id, amount, tx_type, ack_status, metadata, currency, category_id, description, is_settlement)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
         "#,
-        to_sqlx_uuid(auth_user.user_id),
-        to_sqlx_uuid(req.transaction.payee_id),
-        to_sqlx_uuid(req.transaction.group_id),
+        to_sqlx_uuid(payer_id),
+        to_sqlx_uuid(payee_id),
+        to_sqlx_uuid(group_id),
         amount,
-        req.transaction.tx_type as TxType,
+        tx_type as TxType,
         AckStatus::NotAck as AckStatus,
         metadata_json,
+        currency,
+        category_id.map(to_sqlx_uuid),
+        description,
+        is_settlement,
     )
-    .fetch_one(&mut *tx)
+    .fetch_one(&mut **tx)
     .await
-    .on_constraint("transactions_name_key", |_| {
-        Error::unprocessable_entity([("group_name", "group name taken")])
-    })?;
+    .map_insert_err()?;
 
-    sqlx::query!(
+    ctx.ledger
+        .update_ledger_entries(
+            LedgerUpdate {
+                group_id,
+                payer_id,
+                payee_id,
+                amount,
+                currency,
+                tx_id: to_uuid(row.id),
+            },
+            tx,
+        )
+        .await?;
+
+    row.into_transaction()
+}
+
+async fn list_transactions_by_group(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Query(query): Query<ListTxQuery>,
+    page: Pagination,
+) -> Result<Json<TxBody<Vec<Transaction>>>> {
+    if !users::is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(query.group_id))
+        .await?
+        .0
+    {
+        log::info!(
+            "[list_transactions_by_group] user {} is not in group {}",
+            auth_user.user_id,
+            query.group_id,
+        );
+        return Err(Error::Forbidden);
+    }
+
+    let tx_type = query.tx_type.as_deref().map(TxType::from_str).transpose()?;
+    let from = parse_timestamp("from", query.from.as_deref())?;
+    let to = parse_timestamp("to", query.to.as_deref())?;
+
+    // `id` breaks ties between transactions created at the same instant,
+    // so that pages never overlap.
+    let transactions = sqlx::query_as!(
+        TxRow,
         r#"
-            UPDATE "ledgers"
-            SET amount = amount + $1
+            SELECT
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+            FROM "transactions"
             WHERE
-                group_id = $2 AND
-                this_user = $3 AND
-                other_user = $4
+                group_id = $1 AND
+                ($2::txT IS NULL OR tx_type = $2) AND
+                ($3::timestamptz IS NULL OR created_at >= $3) AND
+                ($4::timestamptz IS NULL OR created_at <= $4)
+            ORDER BY created_at DESC, id DESC
+            LIMIT $5
+            OFFSET $6
         "#,
-        amount,
-        to_sqlx_uuid(req.transaction.group_id),
-        to_sqlx_uuid(auth_user.user_id),
-        to_sqlx_uuid(req.transaction.payee_id),
+        to_sqlx_uuid(query.group_id),
+        tx_type as Option<TxType>,
+        from,
+        to,
+        page.limit,
+        page.offset,
     )
-    .execute(&mut *tx)
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(TxRow::into_transaction)
+    .collect::<Result<Vec<_>>>()?;
+
+    Ok(Json(TxBody {
+        transaction: transactions,
+    }))
+}
+
+async fn find_transaction_by_id(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(tx_id): Path<uuid::Uuid>,
+) -> Result<Json<TxBody<Transaction>>> {
+    let transaction = sqlx::query_as!(
+        TxRow,
+        r#"
+            SELECT
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+            FROM "transactions"
+            WHERE id = $1
+        "#,
+        to_sqlx_uuid(tx_id),
+    )
+    .fetch_one(&ctx.db)
     .await
-    .map_err(|e| {
-        log::error!("[create_transaction] fail to update ledger auth_user side: {e}");
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => Error::NotFound,
+        e => Error::Sqlx(e),
+    })?
+    .into_transaction()?;
+
+    // Parties of the transaction can always see it, otherwise the user must be in the group.
+    let is_party =
+        auth_user.user_id == transaction.payer_id || auth_user.user_id == transaction.payee_id;
+    if !is_party
+        && !users::is_user_in_group(
+            ctx.clone(),
+            Path(auth_user.user_id),
+            Path(transaction.group_id),
+        )
+        .await?
+        .0
+    {
+        log::info!(
+            "[find_transaction_by_id] user {} may not view transaction {}",
+            auth_user.user_id,
+            tx_id,
+        );
+        return Err(Error::Forbidden);
+    }
+
+    Ok(Json(TxBody { transaction }))
+}
+
+/// Update the amount, description and/or metadata of a transaction, adjusting the ledger by the difference.
+///
+/// Only the payer may edit, and only while the payee hasn't acknowledged the transaction.
+/// Reversals and settlements can't be edited at all.
+async fn update_transaction(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(tx_id): Path<uuid::Uuid>,
+    Json(req): Json<TxBody<UpdateTx>>,
+) -> Result<Json<TxBody<Transaction>>> {
+    if req.transaction == UpdateTx::default() {
+        return Err(Error::unprocessable_entity([("all", "all fields empty")]));
+    }
+    if let Some(amount) = req.transaction.amount {
+        validate_amount(amount.minor())?;
+    }
+
+    let description = req
+        .transaction
+        .description
+        .as_deref()
+        .map(validate_description)
+        .transpose()?;
+
+    let metadata_json = req
+        .transaction
+        .metadata
+        .map(to_json_value)
+        .transpose()
+        .map_err(|e| {
+            log::error!("[update_transaction] fail converting metadata to json {e:?}");
+            Error::unprocessable_entity([("metadata", "invalid metadata")])
+        })?;
+
+    let mut tx = ctx.db.begin().await?;
+
+    // Lock the row so concurrent edits can't compute their deltas from the same old amount.
+    let existing = sqlx::query_as!(
+        TxRow,
+        r#"
+            SELECT
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+            FROM "transactions"
+            WHERE id = $1
+            FOR UPDATE
+        "#,
+        to_sqlx_uuid(tx_id),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if to_uuid(existing.payer_id) != auth_user.user_id {
+        log::info!(
+            "[update_transaction] user {} is not the payer of transaction {}",
+            auth_user.user_id,
+            tx_id,
+        );
+        return Err(Error::Forbidden);
+    }
+    if existing.ack_status == AckStatus::Ack {
+        return Err(Error::unprocessable_entity([(
+            "ack_status",
+            "acknowledged transactions cannot be edited",
+        )]));
+    }
+    if existing.reverses_tx_id.is_some() {
+        return Err(Error::unprocessable_entity([(
+            "tx",
+            "reversals cannot be edited",
+        )]));
+    }
+    // Settlements were checked against what the payer owed when they were recorded, see
+    // `record_settlement`. Reverse them and record a new one instead.
+    if existing.is_settlement {
+        return Err(Error::unprocessable_entity([(
+            "tx",
+            "settlements cannot be edited",
+        )]));
+    }
+    if is_reversed(&mut tx, tx_id).await? {
+        return Err(Error::unprocessable_entity([("tx", "already reversed")]));
+    }
+
+    // Stored amounts are signed by `tx_type`, same as in `create_transaction`.
+    let amount = match req.transaction.amount.map(Money::minor) {
+        Some(amount) if TxType::Debit == existing.tx_type => -amount,
+        Some(amount) => amount,
+        None => existing.amount,
+    };
+
+    let updated = sqlx::query_as!(
+        TxRow,
+        r#"
+            UPDATE "transactions"
+            SET amount = $2,
+                metadata = coalesce($3, "transactions".metadata),
+                description = CASE
+                    WHEN $4::text IS NULL THEN "transactions".description
+                    ELSE nullif($4, '')
+                END
+            WHERE id = $1
+            RETURNING
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+        "#,
+        to_sqlx_uuid(tx_id),
+        amount,
+        metadata_json,
+        description,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let delta = amount - existing.amount;
+    if delta != 0 {
+        ctx.ledger
+            .update_ledger_entries(
+                LedgerUpdate {
+                    group_id: to_uuid(existing.group_id),
+                    payer_id: to_uuid(existing.payer_id),
+                    payee_id: to_uuid(existing.payee_id),
+                    amount: delta,
+                    currency: &existing.currency,
+                    tx_id,
+                },
+                &mut tx,
+            )
+            .await?;
+    }
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[update_transaction] fail to commit db transaction: {e}");
         Error::Anyhow(anyhow!(""))
     })?;
 
-    sqlx::query!(
+    Ok(Json(TxBody {
+        transaction: updated.into_transaction()?,
+    }))
+}
+
+/// Delete a transaction and reverse its effect on the ledger.
+///
+/// Only the payer of the transaction may delete it.
+async fn delete_transaction(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(tx_id): Path<uuid::Uuid>,
+) -> Result<Json<TxBody<Transaction>>> {
+    let mut tx = ctx.db.begin().await?;
+
+    // Lock the row so a concurrent delete can't reverse the ledger twice.
+    let row = sqlx::query_as!(
+        TxRow,
         r#"
-            UPDATE "ledgers"
-            SET amount = amount - $1
-            WHERE
-                group_id = $2 AND
-                this_user = $3 AND
-                other_user = $4
+            SELECT
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+            FROM "transactions"
+            WHERE id = $1
+            FOR UPDATE
         "#,
-        amount,
-        to_sqlx_uuid(req.transaction.group_id),
-        to_sqlx_uuid(req.transaction.payee_id),
-        to_sqlx_uuid(auth_user.user_id),
+        to_sqlx_uuid(tx_id),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if to_uuid(row.payer_id) != auth_user.user_id {
+        log::info!(
+            "[delete_transaction] user {} is not the payer of transaction {}",
+            auth_user.user_id,
+            tx_id,
+        );
+        return Err(Error::Forbidden);
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM "transactions" WHERE id = $1"#,
+        to_sqlx_uuid(tx_id),
     )
     .execute(&mut *tx)
     .await
-    .map_err(|e| {
-        log::error!("[create_transaction] fail to update ledger payee side: {e}");
+    .on_constraint("transactions_reverses_tx_id_fkey", |_| {
+        Error::unprocessable_entity([("tx", "reversed transactions cannot be deleted")])
+    })?;
+
+    // The stored amount is already signed, so negating it exactly undoes `create_transaction`.
+    ctx.ledger
+        .update_ledger_entries(
+            LedgerUpdate {
+                group_id: to_uuid(row.group_id),
+                payer_id: to_uuid(row.payer_id),
+                payee_id: to_uuid(row.payee_id),
+                amount: -row.amount,
+                currency: &row.currency,
+                tx_id,
+            },
+            &mut tx,
+        )
+        .await?;
+
+    tx.commit().await.map_err(|e| {
+        log::error!("[delete_transaction] fail to commit db transaction: {e}");
         Error::Anyhow(anyhow!(""))
     })?;
 
+    Ok(Json(TxBody {
+        transaction: row.into_transaction()?,
+    }))
+}
+
+/// Undo a transaction by posting an opposing one that references it, so that both the mistake
+/// and its correction stay in the history.
+///
+/// Only the payer of the transaction may reverse it, and only once. Reversals can't be reversed
+/// themselves; delete them instead.
+async fn reverse_transaction(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(tx_id): Path<uuid::Uuid>,
+) -> Result<Created<TxBody<Transaction>>> {
+    let mut tx = ctx.db.begin().await?;
+
+    // Lock the row so concurrent reversals queue up behind each other and see the first one.
+    let original = sqlx::query_as!(
+        TxRow,
+        r#"
+            SELECT
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+            FROM "transactions"
+            WHERE id = $1
+            FOR UPDATE
+        "#,
+        to_sqlx_uuid(tx_id),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if to_uuid(original.payer_id) != auth_user.user_id {
+        log::info!(
+            "[reverse_transaction] user {} is not the payer of transaction {}",
+            auth_user.user_id,
+            tx_id,
+        );
+        return Err(Error::Forbidden);
+    }
+    if original.reverses_tx_id.is_some() {
+        return Err(Error::unprocessable_entity([(
+            "tx",
+            "reversals cannot be reversed",
+        )]));
+    }
+    if is_reversed(&mut tx, tx_id).await? {
+        return Err(Error::unprocessable_entity([("tx", "already reversed")]));
+    }
+
+    // The stored amount is already signed, so negating it exactly undoes the original. Flipping
+    // `tx_type` along with it keeps the amount the API shows positive, like for any other transaction.
+    let tx_type = match original.tx_type {
+        TxType::Credit => TxType::Debit,
+        TxType::Debit => TxType::Credit,
+    };
+    let reversal = sqlx::query_as!(
+        TxRow,
+        r#"
+            INSERT INTO "transactions"
+            (payer_id, payee_id, group_id, amount, tx_type, ack_status, metadata, currency, category_id, description, is_settlement, reverses_tx_id)
+            SELECT
+                payer_id, payee_id, group_id, -amount, $3, $2, metadata, currency, category_id, description, is_settlement, id
+            FROM "transactions"
+            WHERE id = $1
+            RETURNING
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+        "#,
+        to_sqlx_uuid(tx_id),
+        AckStatus::NotAck as AckStatus,
+        tx_type as TxType,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .on_constraint("transactions_reverses_tx_id_key", |_| {
+        Error::unprocessable_entity([("tx", "already reversed")])
+    })?;
+
+    ctx.ledger
+        .update_ledger_entries(
+            LedgerUpdate {
+                group_id: to_uuid(original.group_id),
+                payer_id: to_uuid(original.payer_id),
+                payee_id: to_uuid(original.payee_id),
+                amount: reversal.amount,
+                currency: &original.currency,
+                tx_id: to_uuid(reversal.id),
+            },
+            &mut tx,
+        )
+        .await?;
+
     tx.commit().await.map_err(|e| {
-        log::error!("[create_transaction] fail to commit db transaction: {e}");
+        log::error!("[reverse_transaction] fail to commit db transaction: {e}");
         Error::Anyhow(anyhow!(""))
     })?;
 
+    let location = format!("/api/v1/transactions/{}", reversal.id);
+    Ok(Created::new(TxBody {
+        transaction: reversal.into_transaction()?,
+    })
+    .at(location))
+}
+
+/// Whether a reversal of transaction `tx_id` has been posted.
+async fn is_reversed(tx: &mut sqlx::Transaction<'_, Postgres>, tx_id: uuid::Uuid) -> Result<bool> {
+    let reversed = sqlx::query_scalar!(
+        r#"SELECT exists(SELECT 1 FROM "transactions" WHERE reverses_tx_id = $1) as "exists!""#,
+        to_sqlx_uuid(tx_id),
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(reversed)
+}
+
+/// Acknowledge a transaction as its payee.
+///
+/// Acknowledging is idempotent: acking an already acknowledged transaction just returns it.
+async fn ack_transaction(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(tx_id): Path<uuid::Uuid>,
+) -> Result<Json<TxBody<Transaction>>> {
+    // Only the payee may acknowledge. Doing the check in the `UPDATE` itself
+    // avoids a read-then-write race.
+    let transaction = sqlx::query_as!(
+        TxRow,
+        r#"
+            UPDATE "transactions"
+            SET ack_status = $3
+            WHERE
+                id = $1 AND
+                payee_id = $2
+            RETURNING
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+        "#,
+        to_sqlx_uuid(tx_id),
+        to_sqlx_uuid(auth_user.user_id),
+        AckStatus::Ack as AckStatus,
+    )
+    .fetch_optional(&ctx.db)
+    .await?;
+
+    if let Some(transaction) = transaction {
+        return Ok(Json(TxBody {
+            transaction: transaction.into_transaction()?,
+        }));
+    }
+
+    // Nothing was updated, so either the transaction doesn't exist or the user isn't the payee.
+    sqlx::query_scalar!(
+        r#"SELECT id FROM "transactions" WHERE id = $1"#,
+        to_sqlx_uuid(tx_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    log::info!(
+        "[ack_transaction] user {} is not the payee of transaction {}",
+        auth_user.user_id,
+        tx_id,
+    );
+    Err(Error::Forbidden)
+}
+
+async fn get_group_transactions(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(group_id): Path<uuid::Uuid>,
+    Query(query): Query<GroupTxQuery>,
+    page: Pagination,
+) -> Result<Json<TxBody<Vec<Transaction>>>> {
+    if !users::is_user_in_group(ctx.clone(), Path(auth_user.user_id), Path(group_id))
+        .await?
+        .0
+    {
+        log::info!(
+            "[get_group_transactions] user {} is not in group {}",
+            auth_user.user_id,
+            group_id,
+        );
+        return Err(Error::Forbidden);
+    }
+
+    if let Some(with) = query.with {
+        if !users::is_user_in_group(ctx.clone(), Path(with), Path(group_id))
+            .await?
+            .0
+        {
+            log::info!("[get_group_transactions] user {with} is not in group {group_id}");
+            return Err(Error::Forbidden);
+        }
+    }
+
+    // `Some(None)` only lists transactions without a category.
+    let category = match query.category.as_deref().map(str::trim) {
+        None => None,
+        Some(name) if name.eq_ignore_ascii_case(UNCATEGORIZED) => Some(None),
+        Some(name) => Some(Some(
+            sqlx::query_scalar!(
+                r#"SELECT id FROM "categories" WHERE group_id = $1 AND name = $2"#,
+                to_sqlx_uuid(group_id),
+                name,
+            )
+            .fetch_optional(&ctx.db)
+            .await?
+            .ok_or_else(|| Error::unprocessable_entity([("category", "unknown category")]))?,
+        )),
+    };
+
+    // `id` breaks ties between transactions created at the same instant,
+    // so that pages never overlap.
+    let transactions = sqlx::query_as!(
+        TxRow,
+        r#"
+            SELECT
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+            FROM "transactions"
+            WHERE
+                group_id = $1 AND
+                (NOT $4 OR category_id IS NOT DISTINCT FROM $5) AND
+                (
+                    $6::uuid IS NULL OR
+                    (payer_id = $7 AND payee_id = $6) OR
+                    (payer_id = $6 AND payee_id = $7)
+                )
+            ORDER BY created_at DESC, id DESC
+            LIMIT $2
+            OFFSET $3
+        "#,
+        to_sqlx_uuid(group_id),
+        page.limit,
+        page.offset,
+        category.is_some(),
+        category.flatten(),
+        query.with.map(to_sqlx_uuid),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(TxRow::into_transaction)
+    .collect::<Result<Vec<_>>>()?;
+
     Ok(Json(TxBody {
-        transaction: Transaction {
-            id: to_uuid(txn_id),
-            group_id: req.transaction.group_id,
-            payer_id: auth_user.user_id,
-            payee_id: req.transaction.payee_id,
-            amount: req.transaction.amount,
-            tx_type: req.transaction.tx_type,
-            ack_status: AckStatus::NotAck,
-            metadata: req_metadata,
-        },
+        transaction: transactions,
+    }))
+}
+
+pub async fn get_transactions_by_user(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(user_id): Path<uuid::Uuid>,
+    Query(query): Query<UserTxQuery>,
+    page: Pagination,
+) -> Result<Json<TxBody<Vec<Transaction>>>> {
+    if auth_user.user_id != user_id {
+        return Err(Error::Forbidden);
+    }
+
+    // List all transactions the user is part of, either as payer or payee.
+    // If `tx_type` is given, only list transactions of that type.
+    // `id` breaks ties between transactions created at the same instant,
+    // so that pages never overlap.
+    let transactions = sqlx::query_as!(
+        TxRow,
+        r#"
+            SELECT
+                id, group_id, payer_id, payee_id, amount,
+                tx_type as "tx_type: TxType",
+                ack_status as "ack_status: AckStatus",
+                metadata, currency,
+                category_id,
+                reverses_tx_id,
+                description,
+                is_settlement,
+                created_at
+            FROM "transactions"
+            WHERE
+                (payer_id = $1 OR payee_id = $1) AND
+                ($2::txT IS NULL OR tx_type = $2)
+            ORDER BY created_at DESC, id DESC
+            LIMIT $3
+            OFFSET $4
+        "#,
+        to_sqlx_uuid(user_id),
+        query.tx_type as Option<TxType>,
+        page.limit,
+        page.offset,
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(TxRow::into_transaction)
+    .collect::<Result<Vec<_>>>()?;
+
+    Ok(Json(TxBody {
+        transaction: transactions,
     }))
 }
 
-// pub async fn get_transactions_by_user(
-//     ctx: Extension<ApiContext>,
-//     Path(user_id): Path<String>,
-//     // TODO add optional tx type param. If none - list all.
-// ) -> Result<Json<TxBody<Vec<Transaction>>>> {
-//     let user_id = sqlx::types::Uuid::from_str(&user_id).map_err(|e| {
-//         log::debug!("failed to convert string to uuid: {e}");
-//         Error::unprocessable_entity([("user_id", "invalid user id")])
-//     })?;
-//
-//     let transactions: Vec<Option<Transaction>> = sqlx::query!(
-//         r#"
-//             SELECT
-//                 g.id, g.name
-//             FROM "transactions" g
-//             INNER JOIN "user_transactions" ug
-//             ON g.id = ug.group_id
-//             WHERE ug.user_id = $1"#,
-//         user_id,
-//     )
-//     .fetch(&ctx.db)
-//     .map(|g| {
-//         g.ok().map(|g| Transaction {
-//             id: g.id.to_string(),
-//             name: g.name,
-//         })
-//     })
-//     .collect()
-//     .await;
-//
-//     if transactions.iter().any(|g| g.is_none()) {
-//         log::debug!("[get_transactions_by_user] some transactions are error");
-//         return Err(Error::Anyhow(anyhow!("")));
-//     }
-//
-//     Ok(Json(TxBody {
-//         transaction: transactions.into_iter().map(Option::unwrap).collect(),
-//     }))
-// }
+/// List the transactions the current user is part of across all of their groups, newest first.
+async fn get_activity(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    page: Pagination,
+) -> Result<Json<ActivityBody>> {
+    // Only groups the user still belongs to, so leaving or deleting a group drops it from the feed.
+    // `id` breaks ties between transactions created at the same instant,
+    // so that pages never overlap.
+    let activity = sqlx::query_as!(
+        ActivityRow,
+        r#"
+            SELECT
+                t.id, t.group_id, g.name as group_name,
+                counterparty.id as counterparty_id,
+                counterparty.username as counterparty_username,
+                t.amount, t.currency,
+                t.tx_type as "tx_type: TxType",
+                t.description,
+                t.is_settlement,
+                t.created_at
+            FROM "transactions" t
+            JOIN "groups" g ON g.id = t.group_id
+            JOIN "user_groups" ug ON ug.group_id = t.group_id AND ug.user_id = $1
+            JOIN "users" counterparty ON counterparty.id =
+                CASE WHEN t.payer_id = $1 THEN t.payee_id ELSE t.payer_id END
+            WHERE
+                t.payer_id = $1 OR t.payee_id = $1
+            ORDER BY t.created_at DESC, t.id DESC
+            LIMIT $2
+            OFFSET $3
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+        page.limit,
+        page.offset,
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|row| ActivityEntry {
+        id: to_uuid(row.id),
+        group_id: to_uuid(row.group_id),
+        group_name: row.group_name,
+        counterparty_id: to_uuid(row.counterparty_id),
+        counterparty_username: row.counterparty_username,
+        // Debit amounts are stored negated, see `TxRow::into_transaction`.
+        amount: Money::from_minor(if TxType::Debit == row.tx_type {
+            -row.amount
+        } else {
+            row.amount
+        }),
+        currency: row.currency,
+        tx_type: row.tx_type,
+        description: row.description,
+        is_settlement: row.is_settlement,
+        created_at: row.created_at.into(),
+    })
+    .collect();
+
+    Ok(Json(ActivityBody { activity }))
+}
+
+/// Maps the constraints that can fail when inserting into `transactions` to client errors.
+trait TxInsertResultExt<T> {
+    fn map_insert_err(self) -> Result<T>;
+}
+
+impl<T> TxInsertResultExt<T> for Result<T, sqlx::Error> {
+    fn map_insert_err(self) -> Result<T> {
+        self.on_constraint("transactions_payer_id_fkey", |_| {
+            Error::unprocessable_entity([("payer_id", "user does not exist")])
+        })
+        .on_constraint("transactions_payee_id_fkey", |_| {
+            Error::unprocessable_entity([("payee_id", "user does not exist")])
+        })
+        .on_constraint("transactions_group_id_fkey", |_| {
+            Error::unprocessable_entity([("group_id", "group does not exist")])
+        })
+        .on_constraint("transactions_category_id_fkey", |_| {
+            Error::unprocessable_entity([("category", "category does not exist")])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    #[test]
+    fn validate_amount_rejects_non_positive() {
+        for amount in [0, -1] {
+            let Err(Error::UnprocessableEntity { errors }) = validate_amount(amount) else {
+                panic!("{amount} was accepted");
+            };
+            assert_eq!(errors["amount"], ["must be positive"]);
+        }
+    }
+
+    #[test]
+    fn validate_amount_accepts_positive() {
+        assert!(validate_amount(1).is_ok());
+    }
+
+    // Inserts a transaction of 1.00 MYR, mapping constraint violations like the handlers do.
+    async fn insert(
+        db: &sqlx::PgPool,
+        group_id: sqlx::types::Uuid,
+        payer_id: sqlx::types::Uuid,
+        payee_id: sqlx::types::Uuid,
+        category_id: Option<sqlx::types::Uuid>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO "transactions"
+                (payer_id, payee_id, group_id, amount, tx_type, ack_status, metadata, currency, category_id)
+                VALUES ($1, $2, $3, 100, 'CREDIT', 'NOT_ACK', '{}', 'MYR', $4)
+            "#,
+            payer_id,
+            payee_id,
+            group_id,
+            category_id,
+        )
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_insert_err()
+    }
+
+    #[sqlx::test]
+    async fn map_insert_err_names_the_missing_reference(db: sqlx::PgPool) {
+        let mut users = Vec::new();
+        for username in ["alice", "bob"] {
+            let user_id = sqlx::query_scalar!(
+                r#"insert into "users" (username, email) values ($1, $2) returning id"#,
+                username,
+                format!("{username}@example.com"),
+            )
+            .fetch_one(&db)
+            .await
+            .unwrap();
+            users.push(user_id);
+        }
+        let (alice, bob) = (users[0], users[1]);
+        let group_id = sqlx::query_scalar!(
+            r#"insert into "groups" (name, currency) values ('trip', 'MYR') returning id"#
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        let missing = to_sqlx_uuid(uuid::Uuid::new_v4());
+
+        for (result, field) in [
+            (insert(&db, group_id, missing, bob, None).await, "payer_id"),
+            (
+                insert(&db, group_id, alice, missing, None).await,
+                "payee_id",
+            ),
+            (insert(&db, missing, alice, bob, None).await, "group_id"),
+            (
+                insert(&db, group_id, alice, bob, Some(missing)).await,
+                "category",
+            ),
+        ] {
+            let Err(Error::UnprocessableEntity { errors }) = result else {
+                panic!("missing {field} was not mapped: {result:?}");
+            };
+            assert!(errors.contains_key(field), "{errors:?}");
+        }
+
+        assert!(insert(&db, group_id, alice, bob, None).await.is_ok());
+    }
+
+    /// The amounts of all ledger entries of `group_id`, in a stable order.
+    async fn ledger_amounts(db: &sqlx::PgPool, group_id: uuid::Uuid) -> Vec<i64> {
+        sqlx::query_scalar!(
+            r#"
+                select amount from "ledgers"
+                where group_id = $1
+                order by this_user, other_user, currency
+            "#,
+            to_sqlx_uuid(group_id),
+        )
+        .fetch_all(db)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn reversal_is_positive_and_undoes_the_ledger(db: sqlx::PgPool) {
+        let ctx = ApiContext::for_tests(db);
+        let (_, alice) = ctx.sign_up("alice").await;
+        let (bob_id, _) = ctx.sign_up("bob").await;
+        let group_id = ctx.create_group(&alice, &[bob_id]).await;
+
+        for tx_type in ["Credit", "Debit"] {
+            let (status, body) = ctx
+                .request(
+                    http::Method::POST,
+                    "/api/v1/transactions",
+                    Some(&alice),
+                    Some(serde_json::json!({
+                        "transaction": {
+                            "group_id": group_id,
+                            "payee_id": bob_id,
+                            "amount": "10.00",
+                            "tx_type": tx_type,
+                        }
+                    })),
+                )
+                .await;
+            assert_eq!(status, http::StatusCode::CREATED, "{body}");
+            let tx_id = body["transaction"]["id"].as_str().unwrap();
+
+            let (status, body) = ctx
+                .request(
+                    http::Method::POST,
+                    &format!("/api/v1/transactions/{tx_id}/reverse"),
+                    Some(&alice),
+                    None,
+                )
+                .await;
+            assert_eq!(status, http::StatusCode::CREATED, "{body}");
+            assert_eq!(body["transaction"]["amount"], "10.00");
+            assert_ne!(body["transaction"]["tx_type"], tx_type);
+            assert!(ledger_amounts(&ctx.db, group_id)
+                .await
+                .iter()
+                .all(|&amount| amount == 0));
+
+            // Editing the reversal would make it stop undoing the original.
+            let reversal_id = body["transaction"]["id"].as_str().unwrap();
+            let (status, body) = ctx
+                .request(
+                    http::Method::PUT,
+                    &format!("/api/v1/transactions/{reversal_id}"),
+                    Some(&alice),
+                    Some(serde_json::json!({ "transaction": { "amount": "1.00" } })),
+                )
+                .await;
+            assert_eq!(status, http::StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+            assert_eq!(
+                body["error"]["fields"]["tx"],
+                serde_json::json!(["reversals cannot be edited"])
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn retried_transaction_is_created_once(db: sqlx::PgPool) {
+        let ctx = ApiContext::for_tests(db);
+        let (alice_id, alice) = ctx.sign_up("alice").await;
+        let (bob_id, _) = ctx.sign_up("bob").await;
+        let group_id = ctx.create_group(&alice, &[bob_id]).await;
+
+        let mut tx_ids = Vec::new();
+        for _ in 0..2 {
+            let req = serde_json::from_value(serde_json::json!({
+                "transaction": {
+                    "group_id": group_id,
+                    "payee_id": bob_id,
+                    "amount": "10.00",
+                    "tx_type": "Credit",
+                }
+            }))
+            .unwrap();
+            let res = create_transaction(
+                Extension(ctx.clone()),
+                AuthUser { user_id: alice_id },
+                IdempotencyKey(Some("retry-me".to_string())),
+                Json(req),
+            )
+            .await
+            .unwrap()
+            .into_response();
+            assert_eq!(res.status(), http::StatusCode::CREATED);
+
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            tx_ids.push(body["transaction"]["id"].clone());
+        }
+        assert_eq!(tx_ids[0], tx_ids[1]);
+
+        let count = sqlx::query_scalar!(
+            r#"select count(*) as "count!" from "transactions" where group_id = $1"#,
+            to_sqlx_uuid(group_id),
+        )
+        .fetch_one(&ctx.db)
+        .await
+        .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            ledger_amounts(&ctx.db, group_id)
+                .await
+                .iter()
+                .map(|amount| amount.abs())
+                .sum::<i64>(),
+            2000
+        );
+    }
+
+    #[sqlx::test]
+    async fn transaction_to_oneself_is_rejected(db: sqlx::PgPool) {
+        let ctx = ApiContext::for_tests(db);
+        let (alice_id, alice) = ctx.sign_up("alice").await;
+        let (bob_id, _) = ctx.sign_up("bob").await;
+        let group_id = ctx.create_group(&alice, &[bob_id]).await;
+
+        for (uri, body) in [
+            (
+                "/api/v1/transactions".to_string(),
+                serde_json::json!({
+                    "transaction": {
+                        "group_id": group_id,
+                        "payee_id": alice_id,
+                        "amount": "10.00",
+                        "tx_type": "Credit",
+                    }
+                }),
+            ),
+            (
+                format!("/api/v1/groups/{group_id}/recurring"),
+                serde_json::json!({
+                    "recurring": {
+                        "payee_id": alice_id,
+                        "amount": "10.00",
+                        "tx_type": "Credit",
+                        "period": "Monthly",
+                    }
+                }),
+            ),
+        ] {
+            let (status, body) = ctx
+                .request(http::Method::POST, &uri, Some(&alice), Some(body))
+                .await;
+            assert_eq!(status, http::StatusCode::UNPROCESSABLE_ENTITY, "{uri}");
+            assert!(
+                body["error"]["fields"]["payee_id"].is_array(),
+                "{uri}: {body}"
+            );
+        }
+
+        let written = sqlx::query_scalar!(
+            r#"
+                select
+                    (select count(*) from "transactions")
+                    + (select count(*) from "recurring_transactions")
+                    + (select count(*) from "ledger_events") as "count!"
+            "#
+        )
+        .fetch_one(&ctx.db)
+        .await
+        .unwrap();
+        assert_eq!(written, 0);
+        assert!(ledger_amounts(&ctx.db, group_id)
+            .await
+            .iter()
+            .all(|&amount| amount == 0));
+    }
+
+    #[sqlx::test]
+    async fn paying_oneself_leaves_the_ledger_untouched(db: sqlx::PgPool) {
+        let ctx = ApiContext::for_tests(db);
+        let (alice_id, alice) = ctx.sign_up("alice").await;
+        let (bob_id, _) = ctx.sign_up("bob").await;
+        let group_id = ctx.create_group(&alice, &[bob_id]).await;
+        let to = |payee_id: uuid::Uuid| {
+            serde_json::json!({
+                "group_id": group_id,
+                "payee_id": payee_id,
+                "amount": "10.00",
+                "tx_type": "Credit",
+            })
+        };
+
+        let (status, _) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/transactions",
+                Some(&alice),
+                Some(serde_json::json!({ "transaction": to(bob_id) })),
+            )
+            .await;
+        assert_eq!(status, http::StatusCode::CREATED);
+        let before = ledger_amounts(&ctx.db, group_id).await;
+
+        for (uri, body) in [
+            (
+                "/api/v1/transactions",
+                serde_json::json!({ "transaction": to(alice_id) }),
+            ),
+            // The entry to bob isn't recorded either.
+            (
+                "/api/v1/transactions/batch",
+                serde_json::json!({ "transaction": [to(bob_id), to(alice_id)] }),
+            ),
+        ] {
+            let (status, body) = ctx
+                .request(http::Method::POST, uri, Some(&alice), Some(body))
+                .await;
+            assert_eq!(status, http::StatusCode::UNPROCESSABLE_ENTITY, "{uri}");
+            assert!(
+                body["error"]["fields"]["payee_id"].is_array(),
+                "{uri}: {body}"
+            );
+            assert_eq!(ledger_amounts(&ctx.db, group_id).await, before, "{uri}");
+        }
+    }
+
+    #[sqlx::test]
+    async fn group_transactions_filter_by_category_name(db: sqlx::PgPool) {
+        let ctx = ApiContext::for_tests(db);
+        let (_, alice) = ctx.sign_up("alice").await;
+        let (bob_id, _) = ctx.sign_up("bob").await;
+        let group_id = ctx.create_group(&alice, &[bob_id]).await;
+
+        let (status, body) = ctx
+            .request(
+                http::Method::POST,
+                &format!("/api/v1/groups/{group_id}/categories"),
+                Some(&alice),
+                Some(serde_json::json!({ "category": { "name": "Food" } })),
+            )
+            .await;
+        assert_eq!(status, http::StatusCode::CREATED);
+        let food_id = body["category"]["id"].clone();
+
+        for category_id in [food_id.clone(), serde_json::Value::Null] {
+            let (status, _) = ctx
+                .request(
+                    http::Method::POST,
+                    "/api/v1/transactions",
+                    Some(&alice),
+                    Some(serde_json::json!({
+                        "transaction": {
+                            "group_id": group_id,
+                            "payee_id": bob_id,
+                            "amount": "10.00",
+                            "tx_type": "Credit",
+                            "category_id": category_id,
+                        }
+                    })),
+                )
+                .await;
+            assert_eq!(status, http::StatusCode::CREATED);
+        }
+
+        for (category, expected) in [
+            ("food", food_id),
+            ("Uncategorized", serde_json::Value::Null),
+        ] {
+            let (status, body) = ctx
+                .request(
+                    http::Method::GET,
+                    &format!("/api/v1/groups/{group_id}/transactions?category={category}"),
+                    Some(&alice),
+                    None,
+                )
+                .await;
+            assert_eq!(status, http::StatusCode::OK, "{category}");
+            let transactions = body["transaction"].as_array().unwrap();
+            assert_eq!(transactions.len(), 1, "{category}: {body}");
+            assert_eq!(transactions[0]["category_id"], expected, "{category}");
+        }
+
+        let (status, body) = ctx
+            .request(
+                http::Method::GET,
+                &format!("/api/v1/groups/{group_id}/transactions?category=Rent"),
+                Some(&alice),
+                None,
+            )
+            .await;
+        assert_eq!(status, http::StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"]["fields"]["category"].is_array(), "{body}");
+    }
+}
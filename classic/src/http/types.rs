@@ -1,7 +1,12 @@
+use axum::{
+    http::{header::LOCATION, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 use time::{Format, OffsetDateTime};
 
-use std::fmt::Formatter;
+use std::{fmt::Formatter, str::FromStr};
 
 /// `OffsetDateTime` provides RFC-3339 (ISO-8601 subset) serialization, but the default
 /// `serde::Serialize` implementation produces array of integers, which is great for binary
@@ -18,9 +23,35 @@ use std::fmt::Formatter;
 /// * `cookie::CookieBuilder` (used by Actix-web and `tower-cookies`) bakes-in `time::Duration`
 ///   for setting the expiration
 ///     * not really Chrono's fault but certainly doesn't help.
-#[derive(sqlx::Type)]
+///
+/// SQLx decodes `timestamptz` into a newer version of `OffsetDateTime` than the one used here,
+/// so use the `From` impls below to convert to and from query parameters and results.
 pub struct Timestamptz(pub OffsetDateTime);
 
+/// Parses an RFC 3339 timestamp, e.g. from a query parameter.
+impl FromStr for Timestamptz {
+    type Err = time::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OffsetDateTime::parse(s, Format::Rfc3339).map(Timestamptz)
+    }
+}
+
+impl From<sqlx::types::time::OffsetDateTime> for Timestamptz {
+    fn from(t: sqlx::types::time::OffsetDateTime) -> Self {
+        Timestamptz(OffsetDateTime::from_unix_timestamp_nanos(
+            t.unix_timestamp_nanos(),
+        ))
+    }
+}
+
+impl From<Timestamptz> for sqlx::types::time::OffsetDateTime {
+    fn from(t: Timestamptz) -> Self {
+        sqlx::types::time::OffsetDateTime::from_unix_timestamp_nanos(t.0.unix_timestamp_nanos())
+            .expect("both versions of `OffsetDateTime` support the same range")
+    }
+}
+
 impl Serialize for Timestamptz {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -69,3 +100,43 @@ impl<'de> Deserialize<'de> for Timestamptz {
         deserializer.deserialize_str(StrVisitor)
     }
 }
+
+/// Return this from a handler that creates a resource to respond with `201 Created`
+/// instead of `200 OK`, and a `Location` header pointing at the new resource if it has
+/// a canonical URL.
+pub struct Created<T> {
+    location: Option<String>,
+    body: T,
+}
+
+impl<T> Created<T> {
+    pub fn new(body: T) -> Self {
+        Self {
+            location: None,
+            body,
+        }
+    }
+
+    /// Set the `Location` header, e.g. `/api/v1/groups/<id>`.
+    pub fn at(mut self, location: impl Into<String>) -> Self {
+        self.location = Some(location.into());
+        self
+    }
+}
+
+impl<T: Serialize> IntoResponse for Created<T> {
+    fn into_response(self) -> Response {
+        let mut res = (StatusCode::CREATED, Json(self.body)).into_response();
+
+        if let Some(location) = self.location {
+            match location.parse() {
+                Ok(location) => {
+                    res.headers_mut().insert(LOCATION, location);
+                }
+                Err(e) => log::error!("invalid Location header {location:?}: {e}"),
+            }
+        }
+
+        res
+    }
+}
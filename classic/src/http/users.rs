@@ -1,32 +1,92 @@
-use super::groups;
+use super::groups::{self, SearchQuery};
 use crate::{
     commons::{to_sqlx_uuid, to_uuid},
-    dto::group::{Group, GroupBody},
+    dto::{
+        group::{Group, GroupBody, GroupRole},
+        user::UserProfile,
+    },
     http::{
         error::{Error, ResultExt},
-        extractor::AuthUser,
-        ApiContext, Result,
+        extractor::{AuthToken, AuthUser},
+        types::Created,
+        ApiContext, Pagination, Result,
     },
+    logic::{avatar::AvatarProvider, totp},
 };
 
 use anyhow::{anyhow, Context};
 use argon2::{password_hash::SaltString, Argon2, PasswordHash};
 use axum::{
-    body::HttpBody,
-    extract::{Extension, Path},
-    routing::{get, post},
+    extract::{ConnectInfo, Extension, Multipart, Path, Query},
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
     Json, Router,
 };
 use base64::{engine::general_purpose, Engine as _};
-use hyper::Client;
-use hyper_tls::HttpsConnector;
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+
+use std::{borrow::Cow, net::SocketAddr, time::Duration};
+
+/// Usernames shorter or longer than these, in characters, are rejected.
+const MIN_USERNAME_LENGTH: usize = 3;
+const MAX_USERNAME_LENGTH: usize = 32;
+
+/// How long a password reset token stays valid after it's requested.
+const PASSWORD_RESET_TTL_MINUTES: i32 = 30;
+
+/// How long an email verification token stays valid after it's sent.
+const EMAIL_VERIFICATION_TTL_HOURS: i32 = 48;
+
+/// How many usernames to try for a user signing up with Google before giving up, see
+/// `create_google_user`.
+const GOOGLE_USERNAME_ATTEMPTS: u32 = 5;
+
+/// How often to delete revoked and refresh tokens that have expired.
+const EXPIRED_TOKENS_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The most users a page of search results has, as a search is meant to find someone specific.
+const USER_SEARCH_LIMIT: i64 = 20;
+
+/// Uploaded avatars larger than this are rejected.
+const MAX_AVATAR_BYTES: usize = 1024 * 1024;
+
+/// How many times to try the avatar service before falling back to a placeholder.
+const AVATAR_FETCH_ATTEMPTS: u32 = 3;
+
+/// How long to wait before the first retry of the avatar service, doubled for each retry after it.
+const AVATAR_FETCH_BACKOFF: Duration = Duration::from_millis(200);
 
 pub fn router() -> Router {
     Router::new()
         .route("/v1/users", post(create_user))
+        .route("/v1/users/search", get(search_users))
+        .route("/v1/users/:user_id", get(get_user_profile))
         .route("/v1/users/:user_id/groups", get(get_user_groups))
+        // The path parameter has to be called `user_id` here as well, since the router
+        // doesn't allow different names for parameters at the same position.
+        .route("/v1/users/:user_id/shared-groups", get(get_shared_groups))
+        .route("/v1/users/:user_id/avatar", get(get_avatar))
         .route("/v1/users/login", post(login_user))
+        .route("/v1/users/oauth/google", post(login_with_google))
+        .route("/v1/users/refresh", post(refresh_token))
+        .route("/v1/users/logout", post(logout))
+        .route("/v1/users/logout-all", post(logout_all))
+        .route("/v1/users/verify", get(verify_email))
+        .route("/v1/users/verify/resend", post(resend_email_verification))
+        .route(
+            "/v1/users/password-reset/request",
+            post(request_password_reset),
+        )
+        .route(
+            "/v1/users/password-reset/confirm",
+            post(confirm_password_reset),
+        )
         .route("/v1/me", get(get_current_user).put(update_user))
+        .route("/v1/me/avatar", put(upload_avatar))
+        .route("/v1/me/2fa/enable", post(enable_2fa))
+        .route("/v1/me/2fa/confirm", post(confirm_2fa))
 }
 
 /// A wrapper type for all requests/responses from this module.
@@ -46,6 +106,21 @@ struct NewUser {
 struct LoginUser {
     email: String,
     password: String,
+    /// Required if the user enabled two-factor authentication.
+    totp_code: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct GoogleLogin {
+    /// The ID token from "Sign in with Google".
+    id_token: String,
+    /// Required if the user enabled two-factor authentication.
+    totp_code: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct RefreshUser {
+    refresh_token: String,
 }
 
 #[derive(serde::Deserialize, Default, PartialEq, Eq)]
@@ -56,6 +131,33 @@ struct UpdateUser {
     password: Option<String>,
 }
 
+#[derive(serde::Deserialize)]
+struct PasswordResetRequest {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+struct PasswordResetConfirm {
+    token: String,
+    new_password: String,
+}
+
+#[derive(serde::Serialize)]
+struct TotpSetup {
+    /// An `otpauth://` URI to add to an authenticator app, usually shown as a QR code.
+    provisioning_uri: String,
+}
+
+#[derive(serde::Deserialize)]
+struct TotpConfirm {
+    totp_code: String,
+}
+
+#[derive(serde::Deserialize)]
+struct VerifyEmailQuery {
+    token: String,
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 struct CurrentUser {
     id: String,
@@ -63,20 +165,34 @@ struct CurrentUser {
     token: String,
     username: String,
     image: Option<String>,
+    email_verified: bool,
+    /// Only issued on signup, login and refresh.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
 }
 
 async fn create_user(
     ctx: Extension<ApiContext>,
-    Json(req): Json<UserBody<NewUser>>,
-) -> Result<Json<UserBody<CurrentUser>>> {
+    Json(mut req): Json<UserBody<NewUser>>,
+) -> Result<Created<UserBody<CurrentUser>>> {
+    req.user.email = normalize_email(&req.user.email);
+    req.user.username = req.user.username.trim().to_string();
+    validate_credentials(
+        Some(&req.user.email),
+        Some(&req.user.username),
+        Some(&req.user.password),
+        ctx.config.password_min_length,
+        ctx.config.password_require_complexity,
+    )?;
+
     let password_hash = hash_password(req.user.password).await?;
 
-    let image = get_base64_encoded_svg_image_for_user(&req.user.email)
-        .await
-        .map_err(|e| Error::Anyhow(anyhow!("failed to get user image: {}", e)))?;
+    // Signing up shouldn't depend on the avatar service, so if it's down the avatar is fetched
+    // later by `get_avatar` instead.
+    let image = fetch_avatar(&ctx, &req.user.email).await;
 
-    let user_id = sqlx::query_scalar!(
-        r#"insert into "users" (username, email, image, password_hash) values ($1, $2, $3, $4) returning id"#,
+    let user = sqlx::query!(
+        r#"insert into "users" (username, email, image, password_hash) values ($1, $2, $3, $4) returning id, token_version, email_verified"#,
         req.user.username,
         req.user.email,
         image,
@@ -91,36 +207,106 @@ async fn create_user(
         Error::unprocessable_entity([("email", "email taken")])
     })?;
 
-    Ok(Json(UserBody {
+    let refresh_token = issue_refresh_token(&ctx, &mut *ctx.db.acquire().await?, user.id).await?;
+
+    // Like the avatar, the verification email can be sent again later if this doesn't work out.
+    if let Err(e) = send_email_verification(&ctx, user.id, &req.user.email).await {
+        log::error!(
+            "[create_user] fail to send verification email to user {}: {e:?}",
+            user.id
+        );
+    }
+
+    // There is no URL to fetch a user by id, only `/api/v1/me`, so no `Location` here.
+    Ok(Created::new(UserBody {
         user: CurrentUser {
-            id: user_id.to_string(),
+            id: user.id.to_string(),
             email: req.user.email,
             token: AuthUser {
-                user_id: to_uuid(user_id),
+                user_id: to_uuid(user.id),
             }
-            .to_jwt(&ctx),
+            .to_jwt(&ctx, user.token_version),
             username: req.user.username,
-            image: Some(image),
+            image,
+            email_verified: user.email_verified,
+            refresh_token: Some(refresh_token),
         },
     }))
 }
 
+/// Log in with an email and password, and a TOTP code if the user enabled two-factor
+/// authentication.
+///
+/// After too many failed attempts for the same email or from the same IP address, further
+/// attempts are refused with `429 Too Many Requests` until the lockout is over, see
+/// `Config::login_max_failures`.
 async fn login_user(
     ctx: Extension<ApiContext>,
-    Json(req): Json<UserBody<LoginUser>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(mut req): Json<UserBody<LoginUser>>,
 ) -> Result<Json<UserBody<CurrentUser>>> {
+    req.user.email = normalize_email(&req.user.email);
+
+    // No account can have a malformed email, so don't bother looking it up, or counting it
+    // towards a lockout.
+    if !is_valid_email(&req.user.email) {
+        return Err(Error::unprocessable_entity([("email", "invalid email")]));
+    }
+
+    let email_key = format!("email:{}", req.user.email);
+    let ip_key = format!("ip:{}", addr.ip());
+
+    let retry_after = [&email_key, &ip_key]
+        .into_iter()
+        .filter_map(|key| ctx.login_limiter.locked_out(key))
+        .max();
+    if let Some(retry_after) = retry_after {
+        log::info!("[login_user] refusing login for {email_key} from {ip_key}, locked out");
+        return Err(Error::TooManyRequests { retry_after });
+    }
+
+    let res = try_login(&ctx, req.user).await;
+    match &res {
+        Ok(_) => ctx.login_limiter.reset(&email_key),
+        // Wrong password, unknown email or invalid TOTP code.
+        Err(Error::Unauthorized | Error::UnprocessableEntity { .. }) => {
+            ctx.login_limiter
+                .record_failure(&email_key, ctx.config.login_max_failures);
+            ctx.login_limiter
+                .record_failure(&ip_key, ctx.config.login_max_failures_per_ip);
+        }
+        Err(_) => {}
+    }
+
+    res
+}
+
+async fn try_login(ctx: &ApiContext, req: LoginUser) -> Result<Json<UserBody<CurrentUser>>> {
     let user = sqlx::query!(
         r#"
-            select id, email, username, image, password_hash 
+            select
+                id, email, username, image, password_hash, token_version, email_verified,
+                totp_secret, totp_enabled_at is not null as "totp_enabled!"
             from "users" where email = $1
         "#,
-        req.user.email,
+        req.email,
     )
     .fetch_optional(&ctx.db)
     .await?
     .ok_or(Error::unprocessable_entity([("email", "does not exist")]))?;
 
-    verify_password(req.user.password, user.password_hash).await?;
+    // Users who signed up with Google don't have a password to log in with.
+    let password_hash = user.password_hash.ok_or(Error::Unauthorized)?;
+    verify_password(req.password, password_hash).await?;
+
+    verify_login_totp(
+        ctx,
+        user.id,
+        user.totp_secret.filter(|_| user.totp_enabled),
+        req.totp_code,
+    )?;
+
+    let refresh_token = issue_refresh_token(ctx, &mut *ctx.db.acquire().await?, user.id).await?;
 
     Ok(Json(UserBody {
         user: CurrentUser {
@@ -129,163 +315,1723 @@ async fn login_user(
             token: AuthUser {
                 user_id: to_uuid(user.id),
             }
-            .to_jwt(&ctx),
+            .to_jwt(ctx, user.token_version),
             username: user.username,
             image: user.image,
+            email_verified: user.email_verified,
+            refresh_token: Some(refresh_token),
         },
     }))
 }
 
-async fn get_current_user(
-    auth_user: AuthUser,
+/// Checks `totp_code` when logging in a user who enabled two-factor authentication, i.e. has a
+/// `totp_secret`.
+fn verify_login_totp(
+    ctx: &ApiContext,
+    user_id: sqlx::types::Uuid,
+    totp_secret: Option<Vec<u8>>,
+    totp_code: Option<String>,
+) -> Result<()> {
+    let Some(totp_secret) = totp_secret else {
+        return Ok(());
+    };
+
+    let totp_code = totp_code.ok_or(Error::TotpRequired)?;
+    let totp_secret = totp::decrypt_secret(totp_encryption_key(ctx), &totp_secret)?;
+
+    if !totp::verify(&totp_secret, &totp_code)? {
+        log::info!("[verify_login_totp] invalid totp code for user {user_id}");
+        return Err(Error::unprocessable_entity([("totp_code", "invalid code")]));
+    }
+
+    Ok(())
+}
+
+/// Log in with an ID token from "Sign in with Google", for users who'd rather not have a
+/// password.
+///
+/// The user is matched by email, which Google has to have verified. If there is no such user
+/// yet, one is created without a password, see `create_google_user`. A user who hasn't verified
+/// their email is taken over, dropping their password, sessions and API keys. Either way the
+/// response is the same as for `login_user`.
+///
+/// Invalid tokens count towards the lockout of the client's IP address like failed logins do.
+async fn login_with_google(
     ctx: Extension<ApiContext>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(req): Json<UserBody<GoogleLogin>>,
+) -> Result<Json<UserBody<CurrentUser>>> {
+    if !ctx.google.enabled() {
+        return Err(Error::NotFound);
+    }
+
+    let ip_key = format!("ip:{}", addr.ip());
+    if let Some(retry_after) = ctx.login_limiter.locked_out(&ip_key) {
+        log::info!("[login_with_google] refusing login from {ip_key}, locked out");
+        return Err(Error::TooManyRequests { retry_after });
+    }
+
+    let res = try_login_with_google(&ctx, req.user).await;
+    if let Err(Error::Unauthorized | Error::UnprocessableEntity { .. }) = &res {
+        ctx.login_limiter
+            .record_failure(&ip_key, ctx.config.login_max_failures_per_ip);
+    }
+
+    res
+}
+
+async fn try_login_with_google(
+    ctx: &ApiContext,
+    req: GoogleLogin,
 ) -> Result<Json<UserBody<CurrentUser>>> {
+    let identity = ctx
+        .google
+        .verify(&req.id_token)
+        .await?
+        .ok_or(Error::Unauthorized)?;
+
+    if !identity.email_verified {
+        return Err(Error::unprocessable_entity([(
+            "id_token",
+            "email of the Google account is not verified",
+        )]));
+    }
+    let email = normalize_email(&identity.email);
+
     let user = sqlx::query!(
-        r#"select email, username, image from "users" where id = $1"#,
-        to_sqlx_uuid(auth_user.user_id)
+        r#"
+            select
+                id, username, image, token_version, email_verified, totp_secret,
+                totp_enabled_at is not null as "totp_enabled!"
+            from "users" where email = $1
+        "#,
+        email,
     )
-    .fetch_one(&ctx.db)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => Error::NotFound,
-        e => Error::Sqlx(e),
-    })?;
+    .fetch_optional(&ctx.db)
+    .await?;
+
+    let (user_id, username, image, token_version) = match user {
+        Some(user) if user.email_verified => {
+            verify_login_totp(
+                ctx,
+                user.id,
+                user.totp_secret.filter(|_| user.totp_enabled),
+                req.totp_code,
+            )?;
+
+            (user.id, user.username, user.image, user.token_version)
+        }
+        // Anyone could have signed up with this email without owning it, so Google vouching for
+        // it means the account is taken over by its actual owner: whoever signed up can't get
+        // back in with their password, two-factor authentication, tokens or API keys.
+        Some(user) => {
+            let mut tx = ctx.db.begin().await?;
+
+            let token_version = sqlx::query_scalar!(
+                r#"
+                    update "users"
+                    set
+                        email_verified = true,
+                        password_hash = null,
+                        totp_secret = null,
+                        totp_enabled_at = null,
+                        token_version = token_version + 1
+                    where id = $1
+                    returning token_version
+                "#,
+                user.id,
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            sqlx::query!(
+                r#"delete from "refresh_tokens" where user_id = $1"#,
+                user.id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query!(r#"delete from "api_keys" where user_id = $1"#, user.id)
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+
+            log::info!(
+                "[login_with_google] user {} with an unverified email taken over by google account {}",
+                user.id,
+                identity.subject
+            );
+
+            (user.id, user.username, user.image, token_version)
+        }
+        None => create_google_user(ctx, &email).await?,
+    };
+
+    log::info!(
+        "[login_with_google] user {user_id} logged in as google account {}",
+        identity.subject
+    );
+
+    let refresh_token = issue_refresh_token(ctx, &mut *ctx.db.acquire().await?, user_id).await?;
 
-    dbg!("auth user", auth_user);
     Ok(Json(UserBody {
         user: CurrentUser {
-            id: auth_user.user_id.to_string(),
-            email: user.email,
-            token: auth_user.to_jwt(&ctx),
-            username: user.username,
-            image: user.image,
+            id: user_id.to_string(),
+            email,
+            token: AuthUser {
+                user_id: to_uuid(user_id),
+            }
+            .to_jwt(ctx, token_version),
+            username,
+            image,
+            email_verified: true,
+            refresh_token: Some(refresh_token),
         },
     }))
 }
 
-async fn update_user(
-    ctx: Extension<ApiContext>,
-    auth_user: AuthUser,
-    Json(req): Json<UserBody<UpdateUser>>,
-) -> Result<Json<UserBody<CurrentUser>>> {
-    if req.user == UpdateUser::default() {
-        return get_current_user(auth_user, ctx).await;
+/// Sign up a user with a verified `email` from Google and no password, returning their id,
+/// username, image and token version.
+///
+/// The username is derived from `email`, with a random number appended if it's taken. Users
+/// can change it afterwards.
+async fn create_google_user(
+    ctx: &ApiContext,
+    email: &str,
+) -> Result<(sqlx::types::Uuid, String, Option<String>, i32)> {
+    let image = fetch_avatar(ctx, email).await;
+    let base = username_from_email(email);
+
+    for attempt in 0..GOOGLE_USERNAME_ATTEMPTS {
+        let username = if attempt == 0 {
+            base.clone()
+        } else {
+            format!("{base}{}", rand::thread_rng().gen_range(1000..10000))
+        };
+
+        let res = sqlx::query!(
+            r#"
+                insert into "users" (username, email, image, email_verified)
+                values ($1, $2, $3, true)
+                returning id, token_version
+            "#,
+            username,
+            email,
+            image,
+        )
+        .fetch_one(&ctx.db)
+        .await;
+
+        match res {
+            Ok(user) => {
+                log::info!(
+                    "[create_google_user] user {} signed up with google",
+                    user.id
+                );
+                return Ok((user.id, username, image, user.token_version));
+            }
+            Err(sqlx::Error::Database(e)) if e.constraint() == Some("users_username_key") => {
+                log::debug!("[create_google_user] username {username} taken");
+            }
+            // Someone signed up with the same email at the same time.
+            Err(sqlx::Error::Database(e)) if e.constraint() == Some("users_email_key") => {
+                return Err(Error::unprocessable_entity([("email", "email taken")]));
+            }
+            Err(e) => return Err(e.into()),
+        }
     }
 
-    let password_hash = if let Some(password) = req.user.password {
-        Some(hash_password(password).await?)
+    Err(Error::Anyhow(anyhow!(
+        "no free username for {email} after {GOOGLE_USERNAME_ATTEMPTS} attempts"
+    )))
+}
+
+/// A valid username from the part of `email` before the `@`, leaving room for the number
+/// `create_google_user` may append.
+fn username_from_email(email: &str) -> String {
+    let local = email.split('@').next().unwrap_or_default();
+    let username = local
+        .chars()
+        .filter(|c| is_username_char(*c))
+        .take(MAX_USERNAME_LENGTH - 4)
+        .collect::<String>();
+
+    if username.chars().count() < MIN_USERNAME_LENGTH {
+        format!("user_{username}")
     } else {
-        None
-    };
+        username
+    }
+}
 
-    let user = sqlx::query!(
-        // Optional updates of fields without needing a separate query for each.
+/// Exchange a refresh token for a new login token, so the client doesn't have to log in again
+/// once the login token expired.
+///
+/// Refresh tokens are single-use: the one in the request is invalidated and a new one is returned.
+async fn refresh_token(
+    ctx: Extension<ApiContext>,
+    Json(req): Json<UserBody<RefreshUser>>,
+) -> Result<Json<UserBody<CurrentUser>>> {
+    let mut tx = ctx.db.begin().await?;
+
+    // Deleting the row up front makes the token single-use even under concurrent requests,
+    // and clears it out if it turns out to be expired.
+    let refresh = sqlx::query!(
         r#"
-            update "users"
-            set email = coalesce($1, "users".email),
-                username = coalesce($2, "users".username),
-                password_hash = coalesce($3, "users".password_hash)
-            where id = $4
-            returning id, email, username, image
+            delete from "refresh_tokens"
+            where token_hash = $1
+            returning user_id, expires_at > now() as "valid!"
         "#,
-        req.user.email,
-        req.user.username,
-        password_hash,
-        to_sqlx_uuid(auth_user.user_id)
+        hash_token(&req.user.refresh_token),
     )
-    .fetch_one(&ctx.db)
-    .await
-    .on_constraint("user_username_key", |_| {
-        Error::unprocessable_entity([("username", "username taken")])
-    })
-    .on_constraint("user_email_key", |_| {
-        Error::unprocessable_entity([("email", "email taken")])
-    })?;
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::Unauthorized)?;
+
+    if !refresh.valid {
+        // Commit anyway so the expired token is gone.
+        tx.commit().await?;
+        return Err(Error::Unauthorized);
+    }
+
+    let user = sqlx::query!(
+        r#"select email, username, image, token_version, email_verified from "users" where id = $1"#,
+        refresh.user_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let refresh_token = issue_refresh_token(&ctx, &mut tx, refresh.user_id).await?;
+
+    tx.commit().await?;
 
     Ok(Json(UserBody {
         user: CurrentUser {
-            id: user.id.to_string(),
+            id: refresh.user_id.to_string(),
             email: user.email,
-            token: auth_user.to_jwt(&ctx),
+            token: AuthUser {
+                user_id: to_uuid(refresh.user_id),
+            }
+            .to_jwt(&ctx, user.token_version),
             username: user.username,
             image: user.image,
+            email_verified: user.email_verified,
+            refresh_token: Some(refresh_token),
         },
     }))
 }
 
-async fn get_user_groups(
-    ctx: Extension<ApiContext>,
-    auth_user: AuthUser,
-    Path(user_id): Path<uuid::Uuid>,
-) -> Result<Json<GroupBody<Vec<Group>>>> {
-    groups::get_groups_by_user(ctx, auth_user, Path(user_id)).await
-}
+/// Store a new refresh token for `user_id` and return it.
+async fn issue_refresh_token(
+    ctx: &ApiContext,
+    conn: &mut sqlx::PgConnection,
+    user_id: sqlx::types::Uuid,
+) -> Result<String> {
+    let token = generate_token();
 
-pub async fn is_user_in_group(
-    ctx: Extension<ApiContext>,
-    Path(user_id): Path<uuid::Uuid>,
-    Path(group_id): Path<uuid::Uuid>,
-) -> Result<Json<bool>> {
-    let user_groups = groups::get_groups_by_user(ctx, AuthUser { user_id }, Path(user_id))
-        .await?
-        .0;
-    Ok(Json(user_groups.group.iter().any(|g| g.id == group_id)))
+    sqlx::query!(
+        r#"
+            insert into "refresh_tokens" (user_id, token_hash, expires_at)
+            values ($1, $2, now() + make_interval(secs => $3::bigint))
+        "#,
+        user_id,
+        hash_token(&token),
+        ctx.config.refresh_token_ttl_seconds,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(token)
 }
 
-async fn hash_password(password: String) -> Result<String> {
-    tokio::task::spawn_blocking(move || -> Result<String> {
-        let salt = SaltString::generate(rand::thread_rng());
-        Ok(PasswordHash::generate(Argon2::default(), password, &salt)
-            .map_err(|e| anyhow::anyhow!("failed to generate password hash: {}", e))?
-            .to_string())
-    })
-    .await
-    .context("panic in generating password hash")?
+/// Invalidate the token used for this request.
+async fn logout(auth_token: AuthToken, ctx: Extension<ApiContext>) -> Result<()> {
+    sqlx::query!(
+        r#"
+            insert into "revoked_tokens" (jti, expires_at)
+            values ($1, to_timestamp($2))
+            on conflict (jti) do nothing
+        "#,
+        to_sqlx_uuid(auth_token.jti),
+        auth_token.exp as f64,
+    )
+    .execute(&ctx.db)
+    .await?;
+
+    Ok(())
 }
 
-async fn verify_password(password: String, password_hash: String) -> Result<()> {
-    tokio::task::spawn_blocking(move || -> Result<()> {
-        let hash = PasswordHash::new(&password_hash)
-            .map_err(|e| anyhow::anyhow!("invalid password hash: {}", e))?;
+/// Invalidate every token issued to the current user so far, including the one used for
+/// this request, as well as all of their refresh tokens and API keys.
+async fn logout_all(auth_user: AuthUser, ctx: Extension<ApiContext>) -> Result<()> {
+    let mut tx = ctx.db.begin().await?;
 
-        hash.verify_password(&[&Argon2::default()], password)
-            .map_err(|e| match e {
-                argon2::password_hash::Error::Password => Error::Unauthorized,
-                _ => anyhow::anyhow!("failed to verify password hash: {}", e).into(),
-            })
-    })
-    .await
-    .context("panic in verifying password hash")?
+    sqlx::query!(
+        r#"update "users" set token_version = token_version + 1 where id = $1"#,
+        to_sqlx_uuid(auth_user.user_id)
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"delete from "refresh_tokens" where user_id = $1"#,
+        to_sqlx_uuid(auth_user.user_id)
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"delete from "api_keys" where user_id = $1"#,
+        to_sqlx_uuid(auth_user.user_id)
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
 }
 
-async fn get_base64_encoded_svg_image_for_user(email: &String) -> Result<String> {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, hyper::Body>(https);
-    let mut res = client
-        .get(
-            format!("https://joesch.moe/api/v1/male/{email}")
-                .parse()
-                .map_err(|_| Error::Anyhow(anyhow!("failed to parse profile picture uri")))?,
-        )
-        .await
-        .map_err(|e| Error::Anyhow(anyhow!("failed to get profile picture {}", e)))?;
-
-    let status = res.status();
-    if status.is_success() {
-        let mut full_body: Vec<u8> = Vec::new();
-        while let Some(chunk) = res.body_mut().data().await {
-            let mut chunk = chunk
-                .map_err(|e| Error::Anyhow(anyhow!("chunk fail {}", e)))?
-                .into_iter()
-                .collect::<Vec<u8>>();
-            full_body.append(&mut chunk);
+/// Periodically delete revoked and refresh tokens that have expired, so `revoked_tokens` and
+/// `refresh_tokens` don't grow forever.
+pub(super) fn spawn_expired_tokens_cleanup(ctx: ApiContext) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRED_TOKENS_CLEANUP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let deleted = sqlx::query!(r#"delete from "revoked_tokens" where expires_at < now()"#)
+                .execute(&ctx.db)
+                .await;
+            match deleted {
+                Ok(deleted) => log::debug!(
+                    "[expired_tokens_cleanup] deleted {} expired revoked tokens",
+                    deleted.rows_affected()
+                ),
+                Err(e) => {
+                    log::error!("[expired_tokens_cleanup] fail to delete revoked tokens: {e}")
+                }
+            }
+
+            let deleted = sqlx::query!(r#"delete from "refresh_tokens" where expires_at < now()"#)
+                .execute(&ctx.db)
+                .await;
+            match deleted {
+                Ok(deleted) => log::debug!(
+                    "[expired_tokens_cleanup] deleted {} expired refresh tokens",
+                    deleted.rows_affected()
+                ),
+                Err(e) => {
+                    log::error!("[expired_tokens_cleanup] fail to delete refresh tokens: {e}")
+                }
+            }
         }
-        let encoded = general_purpose::STANDARD.encode(&full_body);
+    });
+}
 
-        Ok(encoded)
-    } else {
-        Err(Error::Anyhow(anyhow!(
-            "get profile picture return err. err={status}",
-        )))
+/// Start enabling two-factor authentication by generating a new secret for the current user.
+///
+/// Login doesn't ask for codes until the user proves they added the secret to their
+/// authenticator app with `confirm_2fa`. Until then, calling this again replaces the secret.
+async fn enable_2fa(
+    auth_token: AuthToken,
+    ctx: Extension<ApiContext>,
+) -> Result<Json<UserBody<TotpSetup>>> {
+    let auth_user = auth_token.user;
+
+    let secret = totp::generate_secret();
+    let encrypted = totp::encrypt_secret(totp_encryption_key(&ctx), &secret)?;
+
+    let email = sqlx::query_scalar!(
+        r#"
+            update "users"
+            set totp_secret = $1
+            where id = $2 and totp_enabled_at is null
+            returning email
+        "#,
+        encrypted,
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .ok_or(Error::unprocessable_entity([("totp", "already enabled")]))?;
+
+    Ok(Json(UserBody {
+        user: TotpSetup {
+            provisioning_uri: totp::provisioning_uri(&secret, &email)?,
+        },
+    }))
+}
+
+/// Finish enabling two-factor authentication with a code generated from the secret that
+/// `enable_2fa` returned. From then on, login requires a code as well.
+async fn confirm_2fa(
+    auth_token: AuthToken,
+    ctx: Extension<ApiContext>,
+    Json(req): Json<UserBody<TotpConfirm>>,
+) -> Result<()> {
+    let auth_user = auth_token.user;
+
+    let user = sqlx::query!(
+        r#"
+            select totp_secret, totp_enabled_at is not null as "totp_enabled!"
+            from "users" where id = $1
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if user.totp_enabled {
+        return Err(Error::unprocessable_entity([("totp", "already enabled")]));
+    }
+    let Some(secret) = user.totp_secret else {
+        return Err(Error::unprocessable_entity([("totp", "not set up")]));
+    };
+
+    let secret = totp::decrypt_secret(totp_encryption_key(&ctx), &secret)?;
+    if !totp::verify(&secret, &req.user.totp_code)? {
+        return Err(Error::unprocessable_entity([("totp_code", "invalid code")]));
+    }
+
+    sqlx::query!(
+        r#"update "users" set totp_enabled_at = now() where id = $1"#,
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .execute(&ctx.db)
+    .await?;
+
+    log::info!(
+        "[confirm_2fa] enabled two-factor authentication for user {}",
+        auth_user.user_id
+    );
+
+    Ok(())
+}
+
+fn totp_encryption_key(ctx: &ApiContext) -> &str {
+    ctx.config
+        .totp_encryption_key
+        .as_deref()
+        .unwrap_or(&ctx.config.hmac_key)
+}
+
+/// Email a single-use token to `email` that `verify_email` accepts to mark it as verified.
+async fn send_email_verification(
+    ctx: &ApiContext,
+    user_id: sqlx::types::Uuid,
+    email: &str,
+) -> Result<()> {
+    let token = generate_token();
+
+    sqlx::query!(
+        r#"
+            insert into "email_verifications" (user_id, token_hash, expires_at)
+            values ($1, $2, now() + make_interval(hours => $3))
+        "#,
+        user_id,
+        hash_token(&token),
+        EMAIL_VERIFICATION_TTL_HOURS,
+    )
+    .execute(&ctx.db)
+    .await?;
+
+    let body = format!(
+        "Use this token to verify your email within {EMAIL_VERIFICATION_TTL_HOURS} hours: {token}"
+    );
+    ctx.mailer
+        .send(email, "Verify your email", &body)
+        .await
+        .context("failed to send verification email")?;
+
+    Ok(())
+}
+
+/// Mark the current user's email as verified using a token from `send_email_verification`.
+///
+/// This is a `GET` so that it works as a link in the email.
+async fn verify_email(
+    ctx: Extension<ApiContext>,
+    Query(query): Query<VerifyEmailQuery>,
+) -> Result<()> {
+    let mut tx = ctx.db.begin().await?;
+
+    // Deleting the row up front makes the token single-use even under concurrent requests,
+    // and clears it out if it turns out to be expired.
+    let verification = sqlx::query!(
+        r#"
+            delete from "email_verifications"
+            where token_hash = $1
+            returning user_id, expires_at > now() as "valid!"
+        "#,
+        hash_token(&query.token),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::Unauthorized)?;
+
+    if !verification.valid {
+        // Commit anyway so the expired token is gone.
+        tx.commit().await?;
+        return Err(Error::Unauthorized);
+    }
+
+    sqlx::query!(
+        r#"update "users" set email_verified = true where id = $1"#,
+        verification.user_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Send another verification email, e.g. if the previous one expired.
+async fn resend_email_verification(auth_user: AuthUser, ctx: Extension<ApiContext>) -> Result<()> {
+    let user = sqlx::query!(
+        r#"select email, email_verified from "users" where id = $1"#,
+        to_sqlx_uuid(auth_user.user_id)
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .ok_or(Error::Unauthorized)?;
+
+    if user.email_verified {
+        return Err(Error::unprocessable_entity([("email", "already verified")]));
+    }
+
+    send_email_verification(&ctx, to_sqlx_uuid(auth_user.user_id), &user.email).await
+}
+
+/// Fail with `Forbidden` if email verification is required and `user_id` hasn't verified theirs.
+pub(super) async fn require_verified_email(ctx: &ApiContext, user_id: uuid::Uuid) -> Result<()> {
+    if !ctx.config.require_email_verification {
+        return Ok(());
+    }
+
+    let verified = sqlx::query_scalar!(
+        r#"select email_verified from "users" where id = $1"#,
+        to_sqlx_uuid(user_id)
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .unwrap_or(false);
+
+    if !verified {
+        return Err(Error::Forbidden);
+    }
+
+    Ok(())
+}
+
+/// Email a single-use token that can be exchanged for a new password.
+///
+/// This succeeds whether or not the email belongs to a user, so it can't be used to find out
+/// which emails are registered.
+async fn request_password_reset(
+    ctx: Extension<ApiContext>,
+    Json(mut req): Json<UserBody<PasswordResetRequest>>,
+) -> Result<()> {
+    req.user.email = normalize_email(&req.user.email);
+
+    let user_id =
+        sqlx::query_scalar!(r#"select id from "users" where email = $1"#, req.user.email,)
+            .fetch_optional(&ctx.db)
+            .await?;
+
+    let Some(user_id) = user_id else {
+        log::info!(
+            "[request_password_reset] no user with email {}",
+            req.user.email
+        );
+        return Ok(());
+    };
+
+    let token = generate_token();
+
+    sqlx::query!(
+        r#"
+            insert into "password_resets" (user_id, token_hash, expires_at)
+            values ($1, $2, now() + make_interval(mins => $3))
+        "#,
+        user_id,
+        hash_token(&token),
+        PASSWORD_RESET_TTL_MINUTES,
+    )
+    .execute(&ctx.db)
+    .await?;
+
+    let body = format!(
+        "Use this token to reset your password within {PASSWORD_RESET_TTL_MINUTES} minutes: {token}"
+    );
+    if let Err(e) = ctx
+        .mailer
+        .send(&req.user.email, "Reset your password", &body)
+        .await
+    {
+        // Failing the request here would reveal that the email is registered.
+        log::error!("[request_password_reset] fail to send reset email to user {user_id}: {e:?}");
+    }
+
+    Ok(())
+}
+
+/// Set a new password using a token from `request_password_reset`.
+///
+/// Every login token and refresh token of the user stops working, as for `logout_all`.
+async fn confirm_password_reset(
+    ctx: Extension<ApiContext>,
+    Json(req): Json<UserBody<PasswordResetConfirm>>,
+) -> Result<()> {
+    validate_credentials(
+        None,
+        None,
+        Some(&req.user.new_password),
+        ctx.config.password_min_length,
+        ctx.config.password_require_complexity,
+    )?;
+
+    let password_hash = hash_password(req.user.new_password).await?;
+
+    let mut tx = ctx.db.begin().await?;
+
+    // Deleting the row up front makes the token single-use even under concurrent requests,
+    // and clears it out if it turns out to be expired.
+    let reset = sqlx::query!(
+        r#"
+            delete from "password_resets"
+            where token_hash = $1
+            returning user_id, expires_at > now() as "valid!"
+        "#,
+        hash_token(&req.user.token),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::Unauthorized)?;
+
+    if !reset.valid {
+        // Commit anyway so the expired token is gone.
+        tx.commit().await?;
+        return Err(Error::Unauthorized);
+    }
+
+    // Log out everywhere and revoke API keys, like `logout_all`, in case the password was reset
+    // because someone else got into the account.
+    sqlx::query!(
+        r#"
+            update "users"
+            set password_hash = $1, token_version = token_version + 1
+            where id = $2
+        "#,
+        password_hash,
+        reset.user_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"delete from "refresh_tokens" where user_id = $1"#,
+        reset.user_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"delete from "api_keys" where user_id = $1"#,
+        reset.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Only accepts login tokens, as the response includes a new one, which would otherwise let an
+/// API key be exchanged for a login token.
+async fn get_current_user(
+    auth_token: AuthToken,
+    ctx: Extension<ApiContext>,
+) -> Result<Json<UserBody<CurrentUser>>> {
+    let auth_user = auth_token.user;
+
+    let user = sqlx::query!(
+        r#"select email, username, image, token_version, email_verified from "users" where id = $1"#,
+        to_sqlx_uuid(auth_user.user_id)
+    )
+    .fetch_one(&ctx.db)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => Error::NotFound,
+        e => Error::Sqlx(e),
+    })?;
+
+    dbg!("auth user", auth_user);
+    Ok(Json(UserBody {
+        user: CurrentUser {
+            id: auth_user.user_id.to_string(),
+            email: user.email,
+            token: auth_user.to_jwt(&ctx, user.token_version),
+            username: user.username,
+            image: user.image,
+            email_verified: user.email_verified,
+            refresh_token: None,
+        },
+    }))
+}
+
+async fn update_user(
+    ctx: Extension<ApiContext>,
+    auth_token: AuthToken,
+    Json(mut req): Json<UserBody<UpdateUser>>,
+) -> Result<Json<UserBody<CurrentUser>>> {
+    req.user.email = req.user.email.as_deref().map(normalize_email);
+    req.user.username = req
+        .user
+        .username
+        .map(|username| username.trim().to_string());
+
+    if req.user == UpdateUser::default() {
+        return get_current_user(auth_token, ctx).await;
+    }
+    let auth_user = auth_token.user;
+
+    validate_credentials(
+        req.user.email.as_deref(),
+        req.user.username.as_deref(),
+        req.user.password.as_deref(),
+        ctx.config.password_min_length,
+        ctx.config.password_require_complexity,
+    )?;
+
+    let password_hash = if let Some(password) = req.user.password {
+        Some(hash_password(password).await?)
+    } else {
+        None
+    };
+
+    let user = sqlx::query!(
+        // Optional updates of fields without needing a separate query for each.
+        r#"
+            update "users"
+            set email = coalesce($1, "users".email),
+                username = coalesce($2, "users".username),
+                password_hash = coalesce($3, "users".password_hash),
+                -- A new email has to be verified again.
+                email_verified = "users".email_verified and ($1 is null or $1 = "users".email)
+            where id = $4
+            returning id, email, username, image, token_version, email_verified
+        "#,
+        req.user.email,
+        req.user.username,
+        password_hash,
+        to_sqlx_uuid(auth_user.user_id)
+    )
+    .fetch_one(&ctx.db)
+    .await
+    .on_constraint("users_username_key", |_| {
+        Error::unprocessable_entity([("username", "username taken")])
+    })
+    .on_constraint("users_email_key", |_| {
+        Error::unprocessable_entity([("email", "email taken")])
+    })?;
+
+    if req.user.email.is_some() && !user.email_verified {
+        send_email_verification(&ctx, user.id, &user.email).await?;
+    }
+
+    Ok(Json(UserBody {
+        user: CurrentUser {
+            id: user.id.to_string(),
+            email: user.email,
+            token: auth_user.to_jwt(&ctx, user.token_version),
+            username: user.username,
+            image: user.image,
+            email_verified: user.email_verified,
+            refresh_token: None,
+        },
+    }))
+}
+
+async fn get_user_groups(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(user_id): Path<uuid::Uuid>,
+    page: Pagination,
+) -> Result<Json<GroupBody<Vec<Group>>>> {
+    groups::get_groups_by_user(ctx, auth_user, Path(user_id), page).await
+}
+
+/// Look up another user, e.g. to show who paid for a transaction.
+///
+/// Only users that share a group with the current user can be looked up.
+async fn get_user_profile(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(user_id): Path<uuid::Uuid>,
+) -> Result<Json<UserBody<UserProfile>>> {
+    let user = sqlx::query!(
+        r#"
+            SELECT
+                u.id, u.username, u.email,
+                exists(
+                    SELECT 1
+                    FROM "user_groups" mine
+                    INNER JOIN "user_groups" theirs
+                    ON theirs.group_id = mine.group_id AND theirs.user_id = u.id
+                    WHERE mine.user_id = $2
+                ) as "shares_group!"
+            FROM "users" u
+            WHERE u.id = $1
+        "#,
+        to_sqlx_uuid(user_id),
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if !user.shares_group && user_id != auth_user.user_id {
+        return Err(Error::Forbidden);
+    }
+
+    Ok(Json(UserBody {
+        user: UserProfile {
+            id: to_uuid(user.id),
+            username: user.username,
+            email: user.email,
+        },
+    }))
+}
+
+/// Find users by a case-insensitive substring of their username or email.
+///
+/// Only the current user and users sharing a group with them are matched, so that search can't be
+/// used to scrape usernames and emails.
+///
+/// Results are paged like other lists, but with at most `USER_SEARCH_LIMIT` users per page.
+async fn search_users(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Query(query): Query<SearchQuery>,
+    page: Pagination,
+) -> Result<Json<UserBody<Vec<UserProfile>>>> {
+    let pattern = query.to_pattern()?;
+
+    // The `case_insensitive` collation doesn't support `ILIKE`, see `migrations/2_users.sql`.
+    let user = sqlx::query!(
+        r#"
+            SELECT u.id, u.username, u.email
+            FROM "users" u
+            WHERE
+                (
+                    (u.username COLLATE "ucs_basic") ILIKE '%' || $1 || '%' OR
+                    (u.email COLLATE "ucs_basic") ILIKE '%' || $1 || '%'
+                ) AND
+                (
+                    u.id = $2 OR
+                    exists(
+                        SELECT 1
+                        FROM "user_groups" mine
+                        INNER JOIN "user_groups" theirs
+                        ON theirs.group_id = mine.group_id AND theirs.user_id = u.id
+                        WHERE mine.user_id = $2
+                    )
+                )
+            ORDER BY u.username, u.id
+            LIMIT $3
+            OFFSET $4
+        "#,
+        pattern,
+        to_sqlx_uuid(auth_user.user_id),
+        page.limit.min(USER_SEARCH_LIMIT),
+        page.offset,
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|u| UserProfile {
+        id: to_uuid(u.id),
+        username: u.username,
+        email: u.email,
+    })
+    .collect();
+
+    Ok(Json(UserBody { user }))
+}
+
+/// List the groups that both the current user and `other_user_id` are members of.
+async fn get_shared_groups(
+    ctx: Extension<ApiContext>,
+    auth_user: AuthUser,
+    Path(other_user_id): Path<uuid::Uuid>,
+) -> Result<Json<GroupBody<Vec<Group>>>> {
+    let group = sqlx::query!(
+        r#"
+            SELECT
+                g.id, g.name, g.currency, g.created_at
+            FROM "groups" g
+            INNER JOIN "user_groups" mine
+            ON mine.group_id = g.id AND mine.user_id = $1
+            INNER JOIN "user_groups" theirs
+            ON theirs.group_id = g.id AND theirs.user_id = $2
+            ORDER BY g.id
+        "#,
+        to_sqlx_uuid(auth_user.user_id),
+        to_sqlx_uuid(other_user_id),
+    )
+    .fetch_all(&ctx.db)
+    .await?
+    .into_iter()
+    .map(|g| Group {
+        id: to_uuid(g.id),
+        name: g.name,
+        currency: g.currency,
+        created_at: g.created_at.into(),
+    })
+    .collect();
+
+    Ok(Json(GroupBody { group }))
+}
+
+pub async fn is_user_in_group(
+    ctx: Extension<ApiContext>,
+    Path(user_id): Path<uuid::Uuid>,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<bool>> {
+    let is_member = sqlx::query_scalar!(
+        r#"
+            SELECT exists(
+                SELECT 1
+                FROM "user_groups" ug
+                WHERE ug.user_id = $1 AND ug.group_id = $2
+            ) as "exists!"
+        "#,
+        to_sqlx_uuid(user_id),
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    Ok(Json(is_member))
+}
+
+/// Whether `user_id` may administer group `group_id`, e.g. rename it or remove other members.
+pub async fn is_group_admin(
+    ctx: Extension<ApiContext>,
+    Path(user_id): Path<uuid::Uuid>,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<bool>> {
+    let is_admin = sqlx::query_scalar!(
+        r#"
+            SELECT exists(
+                SELECT 1
+                FROM "user_groups" ug
+                WHERE
+                    ug.user_id = $1 AND
+                    ug.group_id = $2 AND
+                    ug.role IN ('OWNER', 'ADMIN')
+            ) as "exists!"
+        "#,
+        to_sqlx_uuid(user_id),
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    Ok(Json(is_admin))
+}
+
+/// Fails with `Error::Forbidden` unless `user_id` is a member of group `group_id` with at least
+/// `min_role`.
+pub async fn require_group_role(
+    ctx: &ApiContext,
+    user_id: uuid::Uuid,
+    group_id: uuid::Uuid,
+    min_role: GroupRole,
+) -> Result<()> {
+    let role = sqlx::query_scalar!(
+        r#"
+            SELECT ug.role as "role: GroupRole"
+            FROM "user_groups" ug
+            WHERE ug.user_id = $1 AND ug.group_id = $2
+        "#,
+        to_sqlx_uuid(user_id),
+        to_sqlx_uuid(group_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?;
+
+    match role {
+        Some(role) if role.is_at_least(min_role) => Ok(()),
+        _ => {
+            log::info!(
+                "[require_group_role] user {user_id} is not at least {min_role:?} in group {group_id}"
+            );
+            Err(Error::Forbidden)
+        }
+    }
+}
+
+/// Check the email, username and password a user signs up or updates their profile with,
+/// reporting every invalid field at once.
+///
+/// `password_min_length` and `password_require_complexity` come from the `Config` of the same
+/// name.
+fn validate_credentials(
+    email: Option<&str>,
+    username: Option<&str>,
+    password: Option<&str>,
+    password_min_length: usize,
+    password_require_complexity: bool,
+) -> Result<()> {
+    let mut errors: Vec<(&str, Cow<'static, str>)> = vec![];
+
+    if let Some(email) = email {
+        if !is_valid_email(email) {
+            errors.push(("email", "invalid email".into()));
+        }
+    }
+    if let Some(username) = username {
+        let length = username.chars().count();
+        if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length) {
+            errors.push((
+                "username",
+                format!(
+                    "must be between {MIN_USERNAME_LENGTH} and {MAX_USERNAME_LENGTH} characters"
+                )
+                .into(),
+            ));
+        }
+        if !username.chars().all(is_username_char) {
+            errors.push((
+                "username",
+                "may only contain letters, digits, `_`, `.` and `-`".into(),
+            ));
+        }
+    }
+    if let Some(password) = password {
+        if password.chars().count() < password_min_length {
+            errors.push((
+                "password",
+                format!("must be at least {password_min_length} characters").into(),
+            ));
+        }
+
+        if password_require_complexity {
+            if !password.chars().any(char::is_alphabetic) {
+                errors.push(("password", "must contain a letter".into()));
+            }
+            if password.chars().all(char::is_alphabetic) {
+                errors.push(("password", "must contain a digit or symbol".into()));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(Error::unprocessable_entity(errors));
+    }
+
+    Ok(())
+}
+
+/// Usernames are shown in other users' feeds and searched for, so keep them to characters that
+/// are easy to type and can't be confused with whitespace.
+fn is_username_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+/// Emails are stored and looked up trimmed and lowercased, so that the same address in a different
+/// case can't be registered twice, and logging in doesn't depend on the case it's typed in.
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// A basic sanity check rather than full RFC 5322 validation: something before a single `@`,
+/// and a dotted domain after it, without whitespace.
+fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && !domain.contains('@')
+        && !email.chars().any(char::is_whitespace)
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+}
+
+async fn hash_password(password: String) -> Result<String> {
+    tokio::task::spawn_blocking(move || -> Result<String> {
+        let salt = SaltString::generate(rand::thread_rng());
+        Ok(PasswordHash::generate(Argon2::default(), password, &salt)
+            .map_err(|e| anyhow::anyhow!("failed to generate password hash: {}", e))?
+            .to_string())
+    })
+    .await
+    .context("panic in generating password hash")?
+}
+
+async fn verify_password(password: String, password_hash: String) -> Result<()> {
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let hash = PasswordHash::new(&password_hash)
+            .map_err(|e| anyhow::anyhow!("invalid password hash: {}", e))?;
+
+        hash.verify_password(&[&Argon2::default()], password)
+            .map_err(|e| match e {
+                argon2::password_hash::Error::Password => Error::Unauthorized,
+                _ => anyhow::anyhow!("failed to verify password hash: {}", e).into(),
+            })
+    })
+    .await
+    .context("panic in verifying password hash")?
+}
+
+/// Generate a random, URL-safe token for single-use links such as password resets and invites.
+pub(super) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hash a token from `generate_token` for storage, so a leaked copy of the table it's stored in
+/// can't be used.
+pub(super) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Serve a user's avatar as SVG.
+///
+/// The avatar is fetched and stored on first access if that didn't work out at signup. If the
+/// avatar service is still unavailable, a generated placeholder is served instead.
+async fn get_avatar(
+    ctx: Extension<ApiContext>,
+    _auth_user: AuthUser,
+    Path(user_id): Path<uuid::Uuid>,
+) -> Result<Response> {
+    let user = sqlx::query!(
+        r#"select email, username, image, image_content_type from "users" where id = $1"#,
+        to_sqlx_uuid(user_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .ok_or(Error::NotFound)?;
+
+    if let (Some(image), Some(content_type)) = (&user.image, user.image_content_type) {
+        let image = general_purpose::STANDARD
+            .decode(image)
+            .map_err(|e| Error::Anyhow(anyhow!("invalid avatar stored for user {user_id}: {e}")))?;
+
+        return Ok(([(CONTENT_TYPE, content_type)], image).into_response());
+    }
+
+    let image = match user.image {
+        Some(image) => Some(image),
+        None => {
+            let image = fetch_avatar(&ctx, &user.email).await;
+            if let Some(image) = &image {
+                sqlx::query!(
+                    r#"update "users" set image = $1 where id = $2 and image is null"#,
+                    image,
+                    to_sqlx_uuid(user_id),
+                )
+                .execute(&ctx.db)
+                .await?;
+            }
+            image
+        }
+    };
+
+    let svg = match image {
+        Some(image) => general_purpose::STANDARD
+            .decode(image)
+            .map_err(|e| Error::Anyhow(anyhow!("invalid avatar stored for user {user_id}: {e}")))?,
+        None => default_avatar(&user.username).into_bytes(),
+    };
+
+    Ok(([(CONTENT_TYPE, "image/svg+xml")], svg).into_response())
+}
+
+/// Fetch the avatar for `email`, or `None` if the avatar service is unavailable.
+///
+/// Each attempt is cut off after `avatar_fetch_timeout_ms` so a hung avatar service can't hold up
+/// requests, and failed attempts are retried a few times with backoff.
+async fn fetch_avatar(ctx: &ApiContext, email: &str) -> Option<String> {
+    if !ctx.config.avatar_fetch_enabled {
+        return None;
+    }
+
+    fetch_avatar_from(
+        &*ctx.avatars,
+        Duration::from_millis(ctx.config.avatar_fetch_timeout_ms),
+        email,
+    )
+    .await
+}
+
+/// Like `fetch_avatar`, with each attempt cut off after `timeout`.
+async fn fetch_avatar_from(
+    avatars: &dyn AvatarProvider,
+    timeout: Duration,
+    email: &str,
+) -> Option<String> {
+    let mut backoff = AVATAR_FETCH_BACKOFF;
+
+    for attempt in 1..=AVATAR_FETCH_ATTEMPTS {
+        match tokio::time::timeout(timeout, avatars.fetch(email)).await {
+            Ok(Ok(image)) => return Some(image),
+            Ok(Err(e)) => {
+                log::warn!(
+                    "[fetch_avatar] attempt {attempt} fail to get avatar for {email}: {e:?}"
+                );
+            }
+            Err(_) => {
+                log::warn!("[fetch_avatar] attempt {attempt} timed out getting avatar for {email}");
+            }
+        }
+
+        if attempt < AVATAR_FETCH_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    log::warn!("[fetch_avatar] giving up on avatar for {email}, using a placeholder");
+    None
+}
+
+/// Replace the current user's avatar with an uploaded image.
+///
+/// Expects a `multipart/form-data` body with the image in an `avatar` field.
+async fn upload_avatar(
+    auth_user: AuthUser,
+    ctx: Extension<ApiContext>,
+    mut multipart: Multipart,
+) -> Result<Json<UserBody<CurrentUser>>> {
+    let invalid = |message: &'static str| Error::unprocessable_entity([("avatar", message)]);
+
+    let mut avatar = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| invalid("invalid multipart body"))?
+    {
+        if field.name() != Some("avatar") {
+            continue;
+        }
+
+        avatar = Some(
+            field
+                .bytes()
+                .await
+                .map_err(|_| invalid("invalid multipart body"))?,
+        );
+        break;
+    }
+    let bytes = avatar.ok_or_else(|| invalid("missing"))?;
+
+    if bytes.len() > MAX_AVATAR_BYTES {
+        return Err(Error::unprocessable_entity([(
+            "avatar",
+            format!("must be at most {} KiB", MAX_AVATAR_BYTES / 1024),
+        )]));
+    }
+    // The image is served back with this content type, so don't take the client's word for it.
+    let content_type =
+        avatar_content_type(&bytes).ok_or(invalid("must be a PNG, JPEG, GIF or WebP image"))?;
+
+    let user = sqlx::query!(
+        r#"
+            update "users"
+            set image = $1, image_content_type = $2
+            where id = $3
+            returning email, username, image, token_version, email_verified
+        "#,
+        general_purpose::STANDARD.encode(&bytes),
+        content_type,
+        to_sqlx_uuid(auth_user.user_id),
+    )
+    .fetch_optional(&ctx.db)
+    .await?
+    .ok_or(Error::Unauthorized)?;
+
+    Ok(Json(UserBody {
+        user: CurrentUser {
+            id: auth_user.user_id.to_string(),
+            email: user.email,
+            token: auth_user.to_jwt(&ctx, user.token_version),
+            username: user.username,
+            image: user.image,
+            email_verified: user.email_verified,
+            refresh_token: None,
+        },
+    }))
+}
+
+/// Detect the type of an uploaded avatar from the bytes its file starts with.
+///
+/// SVG isn't allowed, as it can contain scripts.
+fn avatar_content_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// A plain circle with the first letter of `username`, for when there is no avatar.
+fn default_avatar(username: &str) -> String {
+    let initial = username
+        .chars()
+        .find(|c| c.is_ascii_alphanumeric())
+        .map_or('?', |c| c.to_ascii_uppercase());
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="64" height="64" viewBox="0 0 64 64"><circle cx="32" cy="32" r="32" fill="#9ca3af"/><text x="32" y="42" font-family="sans-serif" font-size="28" fill="#ffffff" text-anchor="middle">{initial}</text></svg>"##
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::oauth::{GoogleIdentity, IdTokenVerifier};
+    use async_trait::async_trait;
+    use http::StatusCode;
+    use sqlx::{Pool, Postgres};
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Instant,
+    };
+
+    /// The longest `fetch_avatar_from` may take to give up, given the timeout of each attempt.
+    fn fetch_budget(timeout: Duration) -> Duration {
+        let backoff: Duration = (0..AVATAR_FETCH_ATTEMPTS - 1)
+            .map(|i| AVATAR_FETCH_BACKOFF * 2u32.pow(i))
+            .sum();
+        timeout * AVATAR_FETCH_ATTEMPTS + backoff
+    }
+
+    /// Fails every fetch, or never finishes one if `hang` is set.
+    struct BrokenAvatars {
+        hang: bool,
+        attempts: AtomicU32,
+    }
+
+    #[async_trait]
+    impl AvatarProvider for BrokenAvatars {
+        async fn fetch(&self, _email: &str) -> anyhow::Result<String> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            if self.hang {
+                std::future::pending::<()>().await;
+            }
+            anyhow::bail!("avatar service is down")
+        }
+    }
+
+    #[test]
+    fn normalizes_emails() {
+        assert_eq!(
+            normalize_email("  Alice@Example.COM\n"),
+            "alice@example.com"
+        );
+        assert_eq!(normalize_email("bob@example.com"), "bob@example.com");
+    }
+
+    #[test]
+    fn accepts_valid_emails() {
+        for email in [
+            "alice@example.com",
+            "alice.smith+splitje@mail.example.co.uk",
+            "a@b.c",
+        ] {
+            assert!(is_valid_email(email), "{email}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_emails() {
+        for email in [
+            "",
+            "alice",
+            "alice@",
+            "@example.com",
+            "alice@example",
+            "alice@@example.com",
+            "alice@bob@example.com",
+            "alice@.example.com",
+            "alice@example.com.",
+            "alice smith@example.com",
+            "alice@exam\tple.com",
+        ] {
+            assert!(!is_valid_email(email), "{email:?}");
+        }
+    }
+
+    fn password_errors(password: &str, require_complexity: bool) -> Vec<Cow<'static, str>> {
+        match validate_credentials(None, None, Some(password), 8, require_complexity) {
+            Ok(()) => vec![],
+            Err(Error::UnprocessableEntity { errors }) => errors["password"].clone(),
+            Err(e) => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_short_passwords() {
+        assert_eq!(
+            password_errors("abc1234", false),
+            ["must be at least 8 characters"]
+        );
+        // Characters are counted, not bytes.
+        assert_eq!(
+            password_errors("pässwö1", false),
+            ["must be at least 8 characters"]
+        );
+    }
+
+    #[test]
+    fn rejects_simple_passwords_if_required() {
+        assert_eq!(
+            password_errors("password", true),
+            ["must contain a digit or symbol"]
+        );
+        assert_eq!(password_errors("12345678", true), ["must contain a letter"]);
+        assert!(password_errors("password", false).is_empty());
+    }
+
+    #[test]
+    fn accepts_good_passwords() {
+        for password in ["password1", "pass word", "abcdefgh!", "pässwört1"] {
+            assert!(password_errors(password, true).is_empty(), "{password}");
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_avatar_falls_back_to_none() {
+        for hang in [false, true] {
+            let avatars = BrokenAvatars {
+                hang,
+                attempts: AtomicU32::new(0),
+            };
+            let timeout = Duration::from_millis(50);
+
+            let started = Instant::now();
+            let image = fetch_avatar_from(&avatars, timeout, "alice@example.com").await;
+
+            assert_eq!(image, None);
+            assert_eq!(
+                avatars.attempts.load(Ordering::SeqCst),
+                AVATAR_FETCH_ATTEMPTS
+            );
+            // Leave some slack for the scheduler.
+            assert!(
+                started.elapsed() < fetch_budget(timeout) + Duration::from_millis(500),
+                "took {:?}",
+                started.elapsed()
+            );
+        }
+    }
+
+    /// Log in a user signed up with `ApiContext::sign_up` and return the response body.
+    async fn log_in(ctx: &ApiContext, email: &str) -> serde_json::Value {
+        let (status, body) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/users/login",
+                None,
+                Some(serde_json::json!({
+                    "user": { "email": email, "password": "password123" }
+                })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+
+        body
+    }
+
+    #[sqlx::test]
+    async fn logout_revokes_only_the_token_used(db: Pool<Postgres>) {
+        let ctx = ApiContext::for_tests(db);
+        let (_, token) = ctx.sign_up("alice").await;
+        let other = log_in(&ctx, "alice@example.com").await["user"]["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let (status, _) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/users/logout",
+                Some(&token),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = ctx
+            .request(http::Method::GET, "/api/v1/me", Some(&token), None)
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"]["code"], "INVALID_TOKEN");
+
+        let (status, _) = ctx
+            .request(http::Method::GET, "/api/v1/me", Some(&other), None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn logout_all_revokes_every_token(db: Pool<Postgres>) {
+        let ctx = ApiContext::for_tests(db);
+        let (_, token) = ctx.sign_up("alice").await;
+        let login = log_in(&ctx, "alice@example.com").await;
+        let other = login["user"]["token"].as_str().unwrap();
+        let refresh_token = login["user"]["refresh_token"].as_str().unwrap();
+
+        let (status, _) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/me/api-keys",
+                Some(&token),
+                Some(serde_json::json!({ "api_key": { "name": "ci" } })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, _) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/users/logout-all",
+                Some(&token),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+
+        for token in [token.as_str(), other] {
+            let (status, body) = ctx
+                .request(http::Method::GET, "/api/v1/me", Some(token), None)
+                .await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body["error"]["code"], "INVALID_TOKEN");
+        }
+
+        let (status, _) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/users/refresh",
+                None,
+                Some(serde_json::json!({ "user": { "refresh_token": refresh_token } })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let api_keys = sqlx::query_scalar!(r#"select count(*) as "count!" from "api_keys""#)
+            .fetch_one(&ctx.db)
+            .await
+            .unwrap();
+        assert_eq!(api_keys, 0);
+    }
+
+    #[sqlx::test]
+    async fn email_is_normalized_on_signup_and_login(db: Pool<Postgres>) {
+        let ctx = ApiContext::for_tests(db);
+
+        let (status, body) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/users",
+                None,
+                Some(serde_json::json!({
+                    "user": {
+                        "username": "alice",
+                        "email": " Alice@Example.COM ",
+                        "password": "password123",
+                    }
+                })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        assert_eq!(body["user"]["email"], "alice@example.com");
+
+        let login = log_in(&ctx, "alice@example.com").await;
+        assert_eq!(login["user"]["id"], body["user"]["id"]);
+
+        let login = log_in(&ctx, "ALICE@example.com").await;
+        assert_eq!(login["user"]["id"], body["user"]["id"]);
+    }
+
+    /// Vouches for every ID token as a Google account with the email in it.
+    struct FakeGoogle;
+
+    #[async_trait]
+    impl IdTokenVerifier for FakeGoogle {
+        fn enabled(&self) -> bool {
+            true
+        }
+
+        async fn verify(&self, id_token: &str) -> anyhow::Result<Option<GoogleIdentity>> {
+            Ok(Some(GoogleIdentity {
+                subject: "google-subject".to_string(),
+                email: id_token.to_string(),
+                email_verified: true,
+            }))
+        }
+    }
+
+    #[sqlx::test]
+    async fn google_login_takes_over_unverified_account(db: Pool<Postgres>) {
+        let mut ctx = ApiContext::for_tests(db);
+        ctx.google = Arc::new(FakeGoogle);
+        let (user_id, token) = ctx.sign_up("alice").await;
+
+        let (status, body) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/users/oauth/google",
+                None,
+                Some(serde_json::json!({ "user": { "id_token": "alice@example.com" } })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["user"]["id"], user_id.to_string());
+
+        // Whoever signed up with the email can't get back in.
+        let (status, _) = ctx
+            .request(http::Method::GET, "/api/v1/me", Some(&token), None)
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = ctx
+            .request(
+                http::Method::POST,
+                "/api/v1/users/login",
+                None,
+                Some(serde_json::json!({
+                    "user": { "email": "alice@example.com", "password": "password123" }
+                })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let token = body["user"]["token"].as_str().unwrap();
+        let (status, _) = ctx
+            .request(http::Method::GET, "/api/v1/me", Some(token), None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
     }
 }
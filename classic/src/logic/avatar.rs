@@ -0,0 +1,65 @@
+use async_trait::async_trait;
+use axum::body::HttpBody;
+use base64::{engine::general_purpose, Engine as _};
+use hyper::{client::HttpConnector, Client};
+use hyper_tls::HttpsConnector;
+
+/// A source of avatars for users who haven't uploaded one.
+///
+/// This is a trait so that the avatar service can be swapped out, or stubbed in tests.
+#[async_trait]
+pub trait AvatarProvider: Send + Sync {
+    /// Returns the base64-encoded SVG avatar for `email`.
+    async fn fetch(&self, email: &str) -> anyhow::Result<String>;
+}
+
+/// Fetches avatars over HTTP from `base_url` followed by `path_template`, with `{email}` in the
+/// template replaced by the user's email.
+pub struct HttpAvatarProvider {
+    client: Client<HttpsConnector<HttpConnector>>,
+    base_url: String,
+    path_template: String,
+}
+
+impl HttpAvatarProvider {
+    pub fn new(base_url: String, path_template: String) -> Self {
+        Self {
+            client: Client::builder().build(HttpsConnector::new()),
+            base_url,
+            path_template,
+        }
+    }
+}
+
+#[async_trait]
+impl AvatarProvider for HttpAvatarProvider {
+    async fn fetch(&self, email: &str) -> anyhow::Result<String> {
+        let url = format!(
+            "{}{}",
+            self.base_url.trim_end_matches('/'),
+            self.path_template.replace("{email}", email)
+        );
+        let uri = url
+            .parse()
+            .map_err(|e| anyhow::anyhow!("failed to parse avatar url {url}: {e}"))?;
+
+        let mut res = self
+            .client
+            .get(uri)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to get avatar: {e}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            anyhow::bail!("avatar service returned {status}");
+        }
+
+        let mut full_body: Vec<u8> = Vec::new();
+        while let Some(chunk) = res.body_mut().data().await {
+            let chunk = chunk.map_err(|e| anyhow::anyhow!("failed to read avatar: {e}"))?;
+            full_body.extend_from_slice(&chunk);
+        }
+
+        Ok(general_purpose::STANDARD.encode(&full_body))
+    }
+}
@@ -0,0 +1,81 @@
+use crate::http::{Error, Result};
+
+use std::collections::HashMap;
+
+/// The currency used for groups that don't specify one.
+pub const DEFAULT_CURRENCY: &str = "MYR";
+
+/// ISO 4217 codes of the currencies we accept.
+pub const SUPPORTED_CURRENCIES: &[&str] = &[
+    "AUD", "CNY", "EUR", "GBP", "IDR", "JPY", "MYR", "SGD", "THB", "USD",
+];
+
+// Normalizes `code` to its upper-case ISO 4217 form, rejecting currencies we don't support.
+pub fn validate_currency(code: &str) -> Result<String, Error> {
+    let code = code.trim().to_uppercase();
+
+    if !SUPPORTED_CURRENCIES.contains(&code.as_str()) {
+        return Err(Error::unprocessable_entity([(
+            "currency",
+            "unsupported currency",
+        )]));
+    }
+
+    Ok(code)
+}
+
+/// A source of exchange rates between currencies.
+///
+/// This is a trait so that the source can be swapped out, e.g. for a live rates API,
+/// or for fixed rates in tests.
+pub trait RatesProvider: Send + Sync {
+    /// How many units of `to` one unit of `from` is worth, if known.
+    fn rate(&self, from: &str, to: &str) -> Option<f64>;
+}
+
+/// Fixed, approximate exchange rates for all `SUPPORTED_CURRENCIES`.
+///
+/// Good enough to show a rough total across currencies, but not for actually moving money.
+pub struct StaticRates {
+    /// The value of one unit of each currency in US dollars.
+    usd_value: HashMap<&'static str, f64>,
+}
+
+impl Default for StaticRates {
+    fn default() -> Self {
+        Self {
+            usd_value: HashMap::from([
+                ("AUD", 0.66),
+                ("CNY", 0.14),
+                ("EUR", 1.08),
+                ("GBP", 1.27),
+                ("IDR", 0.000064),
+                ("JPY", 0.0067),
+                ("MYR", 0.21),
+                ("SGD", 0.74),
+                ("THB", 0.028),
+                ("USD", 1.0),
+            ]),
+        }
+    }
+}
+
+impl RatesProvider for StaticRates {
+    fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+
+        Some(self.usd_value.get(from)? / self.usd_value.get(to)?)
+    }
+}
+
+// Converts `amount` from currency `from` to currency `to`, rounding to the nearest unit.
+pub fn convert(rates: &dyn RatesProvider, amount: i64, from: &str, to: &str) -> Result<i64, Error> {
+    let rate = rates.rate(from, to).ok_or_else(|| {
+        log::error!("[convert] no exchange rate from {from} to {to}");
+        Error::unprocessable_entity([("currency", "no exchange rate available")])
+    })?;
+
+    Ok((amount as f64 * rate).round() as i64)
+}
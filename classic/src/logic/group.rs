@@ -1,11 +1,15 @@
 use crate::{
     commons::{to_sqlx_uuid, to_uuid},
-    dto::{group::Group, user::User},
-    http::{extractor::AuthUser, Error, Result, ResultExt},
+    dto::{
+        group::{Group, GroupRole},
+        money::Money,
+        user::User,
+    },
+    http::{extractor::AuthUser, Error, Pagination, Result, ResultExt},
 };
 
-use anyhow::anyhow;
-use futures::StreamExt;
+use anyhow::{anyhow, Context};
+use futures::TryStreamExt;
 use sqlx::{self, Pool, Postgres, Transaction};
 
 use super::ledger::{self};
@@ -14,21 +18,43 @@ pub trait GroupsHandler {
     fn create_group(
         &self,
         name: String,
+        currency: String,
         owner: AuthUser,
     ) -> impl std::future::Future<Output = Result<Group, Error>> + Send;
 
     fn add_user_to_group(
         &self,
         user: &AuthUser,
-        group: &Group,
+        group_id: &uuid::Uuid,
+        role: GroupRole,
         tx: Option<&mut Transaction<'_, Postgres>>,
     ) -> impl std::future::Future<Output = Result<uuid::Uuid, Error>> + Send;
 
+    fn add_users_to_group(
+        &self,
+        user_ids: &[uuid::Uuid],
+        group_id: &uuid::Uuid,
+        role: GroupRole,
+    ) -> impl std::future::Future<Output = Result<Vec<uuid::Uuid>, Error>> + Send;
+
     fn get_users_by_group(
         &self,
         group_id: &uuid::Uuid,
+        page: Option<Pagination>,
         tx: Option<&mut Transaction<'_, Postgres>>,
     ) -> impl std::future::Future<Output = Result<Vec<User>, Error>> + Send;
+
+    fn remove_user_from_group(
+        &self,
+        user_id: &uuid::Uuid,
+        group_id: &uuid::Uuid,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+
+    fn leave_group(
+        &self,
+        user: &AuthUser,
+        group_id: &uuid::Uuid,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
 }
 
 pub struct Handler {
@@ -40,16 +66,163 @@ impl Handler {
     pub fn new(db: Pool<Postgres>, ledger_handler: ledger::Handler) -> Self {
         Self { db, ledger_handler }
     }
+
+    // Does the work of `GroupsHandler::add_user_to_group` within `tx`, except for the ledger
+    // entries: callers should call `ledger::Handler::ensure_ledger_complete` once they're done
+    // adding members.
+    //
+    // Fails if `user_id` is already a member, which is decided by the insert itself so that
+    // concurrent joins can't both succeed.
+    async fn add_user_to_group_inner(
+        &self,
+        user_id: uuid::Uuid,
+        group_id: uuid::Uuid,
+        role: GroupRole,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<uuid::Uuid, Error> {
+        // Joins to the same group wait for each other, otherwise two users joining at once
+        // wouldn't see each other and miss their ledger entries against each other.
+        sqlx::query_scalar!(
+            r#"select id from "groups" where id = $1 for no key update"#,
+            to_sqlx_uuid(group_id),
+        )
+        .fetch_optional(&mut **tx)
+        .await?
+        .ok_or_else(|| Error::unprocessable_entity([("group", "group does not exist")]))?;
+
+        let user_group_id = sqlx::query_scalar!(
+            r#"
+            insert into "user_groups" (user_id, group_id, role) values ($1, $2, $3)
+            on conflict (user_id, group_id) do nothing
+            returning id
+            "#,
+            to_sqlx_uuid(user_id),
+            to_sqlx_uuid(group_id),
+            role as GroupRole,
+        )
+        .fetch_optional(&mut **tx)
+        .await
+        .on_constraint("user_groups_user_id_fkey", |_| {
+            Error::unprocessable_entity([("user", "user does not exist")])
+        })
+        .on_constraint("user_groups_group_id_fkey", |_| {
+            Error::unprocessable_entity([("group", "group does not exist")])
+        })?
+        .ok_or_else(|| Error::unprocessable_entity([("user", "already in group")]))?;
+
+        Ok(to_uuid(user_group_id))
+    }
+
+    // `add_user_to_group_inner` followed by `ledger::Handler::ensure_ledger_complete`.
+    async fn join_group(
+        &self,
+        user_id: uuid::Uuid,
+        group_id: uuid::Uuid,
+        role: GroupRole,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<uuid::Uuid, Error> {
+        let user_group_id = self
+            .add_user_to_group_inner(user_id, group_id, role, tx)
+            .await?;
+        self.ledger_handler
+            .ensure_ledger_complete(group_id, tx)
+            .await?;
+
+        Ok(user_group_id)
+    }
+
+    // Fails unless every one of the pairwise ledger entries of `user_id` in group `group_id` is
+    // zero, locking them until `tx` ends.
+    //
+    // Netting out to zero isn't enough: if A owes B and C owes A the same amount, deleting A's
+    // entries would erase what C owes B through A.
+    async fn ensure_settled_up(
+        &self,
+        user_id: &uuid::Uuid,
+        group_id: &uuid::Uuid,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), Error> {
+        let amounts = self
+            .ledger_handler
+            .lock_ledger_amounts(*group_id, *user_id, tx)
+            .await?;
+        // List every outstanding amount so the user knows whom to settle up with.
+        let outstanding = amounts
+            .iter()
+            .filter(|entry| entry.amount != 0)
+            .map(|entry| {
+                let (verb, preposition) = if entry.amount < 0 {
+                    ("owes", "to")
+                } else {
+                    ("is owed", "by")
+                };
+                (
+                    "balance",
+                    format!(
+                        "user {verb} {} {} {preposition} {}",
+                        Money::from_minor(entry.amount.abs()),
+                        entry.currency,
+                        entry.other_user
+                    ),
+                )
+            })
+            .collect::<Vec<_>>();
+        if !outstanding.is_empty() {
+            return Err(Error::unprocessable_entity(outstanding));
+        }
+
+        Ok(())
+    }
+
+    // Delete the membership of `user_id` in `group_id` and all of their ledger entries in it.
+    //
+    // The group's owner can't be removed, since no one would be left to administer the group.
+    // `tx` should be rolled back if this fails.
+    async fn delete_membership(
+        &self,
+        user_id: &uuid::Uuid,
+        group_id: &uuid::Uuid,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), Error> {
+        let role = sqlx::query_scalar!(
+            r#"
+            DELETE FROM "user_groups"
+            WHERE user_id = $1 AND group_id = $2
+            RETURNING role as "role: GroupRole"
+            "#,
+            to_sqlx_uuid(*user_id),
+            to_sqlx_uuid(*group_id),
+        )
+        .fetch_optional(&mut **tx)
+        .await?
+        .ok_or(Error::NotFound)?;
+        if role == GroupRole::Owner {
+            return Err(Error::unprocessable_entity([(
+                "role",
+                "the group's owner can't leave it, delete the group instead",
+            )]));
+        }
+
+        self.ledger_handler
+            .delete_ledger_entries(*group_id, *user_id, tx)
+            .await
+    }
 }
 
 impl GroupsHandler for Handler {
-    // Creates a group with `name` and add user `owner` to the group.
-    async fn create_group(&self, group_name: String, owner: AuthUser) -> Result<Group, Error> {
+    // Creates a group with `name` and `currency` and add user `owner` to the group.
+    async fn create_group(
+        &self,
+        group_name: String,
+        currency: String,
+        owner: AuthUser,
+    ) -> Result<Group, Error> {
         let mut tx = self.db.begin().await?;
 
-        let group_id = sqlx::query_scalar!(
-            r#"insert into "groups" (name) values ($1) returning id"#,
+        let row = sqlx::query!(
+            r#"insert into "groups" (name, currency) values ($1, $2) returning id, created_at"#,
             group_name,
+            currency,
         )
         .fetch_one(&mut *tx)
         .await
@@ -58,11 +231,16 @@ impl GroupsHandler for Handler {
         })?;
 
         let group = Group {
-            id: to_uuid(group_id),
+            id: to_uuid(row.id),
             name: group_name,
+            currency,
+            created_at: row.created_at.into(),
         };
 
-        if let Err(e) = self.add_user_to_group(&owner, &group, Some(&mut tx)).await {
+        if let Err(e) = self
+            .add_user_to_group(&owner, &group.id, GroupRole::Owner, Some(&mut tx))
+            .await
+        {
             log::error!("[create_group] fail to add user to group: {e:?}");
             let _ = tx.rollback().await;
             return Err(Error::Anyhow(anyhow!("")));
@@ -76,94 +254,127 @@ impl GroupsHandler for Handler {
         Ok(group)
     }
 
-    // Add user `user` to group `group`,
+    // Add user `user` to group `group_id` as `role`,
     // then initializes ledger entries for `user` against other members of the group.
+    //
+    // Fails if `user` is already a member.
     async fn add_user_to_group(
         &self,
         user: &AuthUser,
-        group: &Group,
+        group_id: &uuid::Uuid,
+        role: GroupRole,
         tx: Option<&mut Transaction<'_, Postgres>>,
     ) -> Result<uuid::Uuid, Error> {
-        let group_id = group.id;
-        let user_id = user.user_id;
+        // Use given transaction if present, otherwise begin a new transaction.
+        if let Some(tx) = tx {
+            self.join_group(user.user_id, *group_id, role, tx).await
+        } else {
+            let mut tx = self.db.begin().await?;
+            let user_group_id = self
+                .join_group(user.user_id, *group_id, role, &mut tx)
+                .await?;
+            tx.commit().await?;
 
-        let query = sqlx::query_scalar!(
-            r#"insert into "user_groups" (user_id, group_id) values ($1, $2) returning id"#,
-            to_sqlx_uuid(user.user_id),
-            to_sqlx_uuid(group_id),
-        );
+            Ok(user_group_id)
+        }
+    }
 
-        // Use given transaction if present, otherwise begin a new transaction.
-        let user_group_id = if let Some(tx) = tx {
-            let user_group_id = query
-                .fetch_one(&mut **tx)
-                .await
-                .on_constraint("user_groups_user_id_fkey", |_| {
-                    Error::unprocessable_entity([("user", "user does not exist")])
-                })
-                .on_constraint("user_groups_group_id_fkey", |_| {
-                    Error::unprocessable_entity([("group", "group does not exist")])
-                })?;
-
-            let other_users_in_group_ids = self
-                .get_users_by_group(&group_id, Some(tx))
-                .await?
-                .iter()
-                .map(|u| u.id)
-                .filter(|id| id != &user_id)
-                .collect::<Vec<_>>();
+    // Add users `user_ids` to group `group_id` as `role` in a single transaction, returning
+    // their membership ids in the same order.
+    //
+    // Ledger entries are initialized between every pair of members once all of them are added,
+    // new ones included. Nothing is added if any of the users doesn't exist or is already a
+    // member.
+    async fn add_users_to_group(
+        &self,
+        user_ids: &[uuid::Uuid],
+        group_id: &uuid::Uuid,
+        role: GroupRole,
+    ) -> Result<Vec<uuid::Uuid>, Error> {
+        let ids = user_ids
+            .iter()
+            .map(|id| to_sqlx_uuid(*id))
+            .collect::<Vec<_>>();
 
-            self.ledger_handler
-                .init_ledger_entries(group_id, user.user_id, other_users_in_group_ids, tx)
-                .await?;
+        let mut tx = self.db.begin().await?;
 
-            user_group_id
-        } else {
-            let mut tx = self.db.begin().await?;
-            let user_group_id = query
-                .fetch_one(&mut *tx)
-                .await
-                .on_constraint("user_groups_user_id_fkey", |_| {
-                    Error::unprocessable_entity([("user", "user does not exist")])
-                })
-                .on_constraint("user_groups_group_id_fkey", |_| {
-                    Error::unprocessable_entity([("group", "group does not exist")])
-                })?;
-
-            let other_users_in_group_ids = self
-                .get_users_by_group(&group_id, Some(&mut tx))
-                .await?
-                .iter()
-                .map(|u| u.id)
-                .filter(|id| id != &user_id)
-                .collect::<Vec<_>>();
+        let missing = sqlx::query_scalar!(
+            r#"
+            SELECT ids.id as "id!"
+            FROM unnest($1::uuid[]) AS ids(id)
+            WHERE NOT exists(SELECT 1 FROM "users" u WHERE u.id = ids.id)
+            "#,
+            &ids,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        if !missing.is_empty() {
+            return Err(Error::unprocessable_entity(missing.into_iter().map(|id| {
+                ("user_ids", format!("user {} does not exist", to_uuid(id)))
+            })));
+        }
 
-            self.ledger_handler
-                .init_ledger_entries(group_id, user.user_id, other_users_in_group_ids, &mut tx)
-                .await?;
+        // `add_user_to_group_inner` would fail on the first of them anyway, but this lists them all.
+        let existing = sqlx::query_scalar!(
+            r#"
+            SELECT user_id
+            FROM "user_groups"
+            WHERE group_id = $1 AND user_id = ANY($2)
+            "#,
+            to_sqlx_uuid(*group_id),
+            &ids,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        if !existing.is_empty() {
+            return Err(Error::unprocessable_entity(existing.into_iter().map(
+                |id| {
+                    (
+                        "user_ids",
+                        format!("user {} is already in group", to_uuid(id)),
+                    )
+                },
+            )));
+        }
 
-            tx.commit().await?;
+        let mut user_group_ids = Vec::with_capacity(user_ids.len());
+        for user_id in user_ids {
+            user_group_ids.push(
+                self.add_user_to_group_inner(*user_id, *group_id, role, &mut tx)
+                    .await?,
+            );
+        }
+        self.ledger_handler
+            .ensure_ledger_complete(*group_id, &mut tx)
+            .await?;
 
-            user_group_id
-        };
+        tx.commit().await?;
 
-        Ok(to_uuid(user_group_id))
+        Ok(user_group_ids)
     }
 
+    // List the members of group `group_id` ordered by id, all of them if `page` is `None`.
     async fn get_users_by_group(
         &self,
         group_id: &uuid::Uuid,
+        page: Option<Pagination>,
         tx: Option<&mut Transaction<'_, Postgres>>,
     ) -> Result<Vec<User>, Error> {
         let query = sqlx::query!(
             r#"
             SELECT
-                u.id, u.username, u.email
+                u.id, u.username, u.email, ug.role as "role: GroupRole"
             FROM "users" u
             INNER JOIN "user_groups" ug
             ON u.id = ug.user_id
-            WHERE ug.group_id = $1"#,
+            WHERE ug.group_id = $1
+            ORDER BY u.id
+            LIMIT $2
+            OFFSET $3"#,
             to_sqlx_uuid(*group_id),
+            // `LIMIT NULL` and `OFFSET NULL` are the same as leaving them out.
+            page.map(|p| p.limit),
+            page.map(|p| p.offset),
         );
 
         let query_stream = if let Some(tx) = tx {
@@ -172,22 +383,367 @@ impl GroupsHandler for Handler {
             query.fetch(&self.db)
         };
 
-        let users: Vec<Option<User>> = query_stream
-            .map(|u| {
-                u.ok().map(|u| User {
-                    id: to_uuid(u.id),
-                    username: u.username,
-                    email: u.email,
-                })
+        let users = query_stream
+            .map_ok(|u| User {
+                id: to_uuid(u.id),
+                username: u.username,
+                email: u.email,
+                role: u.role,
             })
-            .collect()
-            .await;
+            .try_collect()
+            .await
+            .with_context(|| format!("failed to get users of group {group_id}"))?;
 
-        if users.iter().any(|u| u.is_none()) {
-            log::debug!("[get_users_by_group] some users are error");
-            return Err(Error::Anyhow(anyhow!("")));
+        Ok(users)
+    }
+    // Remove user `user_id` from group `group_id` along with all of their ledger entries
+    // in the group. Users that still owe or are owed money, in any currency, can't be removed,
+    // see `Handler::ensure_settled_up`.
+    async fn remove_user_from_group(
+        &self,
+        user_id: &uuid::Uuid,
+        group_id: &uuid::Uuid,
+    ) -> Result<(), Error> {
+        let mut tx = self.db.begin().await?;
+
+        self.ensure_settled_up(user_id, group_id, &mut tx).await?;
+
+        self.delete_membership(user_id, group_id, &mut tx).await?;
+
+        tx.commit().await.map_err(|e| {
+            log::error!("[remove_user_from_group] fail to commit db transaction: {e:?}");
+            Error::Anyhow(anyhow!(""))
+        })?;
+
+        Ok(())
+    }
+
+    // Remove `user` from group `group_id` on their own behalf, once they've settled up like
+    // for `remove_user_from_group`.
+    async fn leave_group(&self, user: &AuthUser, group_id: &uuid::Uuid) -> Result<(), Error> {
+        let mut tx = self.db.begin().await?;
+
+        self.ensure_settled_up(&user.user_id, group_id, &mut tx)
+            .await?;
+        self.delete_membership(&user.user_id, group_id, &mut tx)
+            .await?;
+
+        tx.commit().await.map_err(|e| {
+            log::error!("[leave_group] fail to commit db transaction: {e:?}");
+            Error::Anyhow(anyhow!(""))
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_user(db: &Pool<Postgres>, username: &str) -> AuthUser {
+        let user_id = sqlx::query_scalar!(
+            r#"insert into "users" (username, email) values ($1, $2) returning id"#,
+            username,
+            format!("{username}@example.com"),
+        )
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        AuthUser {
+            user_id: to_uuid(user_id),
+        }
+    }
+
+    // `payee` owes `payer` 0.10 MYR.
+    async fn record(handler: &Handler, group_id: uuid::Uuid, payer: AuthUser, payee: AuthUser) {
+        let mut tx = handler.db.begin().await.unwrap();
+        handler
+            .ledger_handler
+            .update_ledger_entries(
+                ledger::LedgerUpdate {
+                    group_id,
+                    payer_id: payer.user_id,
+                    payee_id: payee.user_id,
+                    amount: 10,
+                    currency: "MYR",
+                    tx_id: uuid::Uuid::new_v4(),
+                },
+                &mut tx,
+            )
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn remove_user_refuses_offsetting_balances(db: Pool<Postgres>) {
+        let handler = Handler::new(db.clone(), ledger::Handler::new());
+        let a = create_user(&db, "alice").await;
+        let b = create_user(&db, "bob").await;
+        let c = create_user(&db, "carol").await;
+        let d = create_user(&db, "dave").await;
+
+        let group = handler
+            .create_group("trip".to_string(), "MYR".to_string(), b)
+            .await
+            .unwrap();
+        for user in [&a, &c, &d] {
+            handler
+                .add_user_to_group(user, &group.id, GroupRole::Member, None)
+                .await
+                .unwrap();
+        }
+
+        // A owes B 10 and C owes A 10, so A nets out to zero.
+        record(&handler, group.id, b, a).await;
+        record(&handler, group.id, a, c).await;
+
+        let err = handler
+            .remove_user_from_group(&a.user_id, &group.id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::UnprocessableEntity { .. }));
+
+        let outstanding = sqlx::query_scalar!(
+            r#"select count(*) as "count!" from "ledgers" where group_id = $1 and amount <> 0"#,
+            to_sqlx_uuid(group.id),
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(outstanding, 4);
+
+        // D has nothing outstanding with anyone.
+        handler
+            .remove_user_from_group(&d.user_id, &group.id)
+            .await
+            .unwrap();
+    }
+
+    #[sqlx::test]
+    async fn add_user_refuses_existing_member(db: Pool<Postgres>) {
+        let handler = Handler::new(db.clone(), ledger::Handler::new());
+        let a = create_user(&db, "alice").await;
+        let b = create_user(&db, "bob").await;
+
+        let group = handler
+            .create_group("trip".to_string(), "MYR".to_string(), a)
+            .await
+            .unwrap();
+        handler
+            .add_user_to_group(&b, &group.id, GroupRole::Member, None)
+            .await
+            .unwrap();
+
+        for user in [&a, &b] {
+            let Err(Error::UnprocessableEntity { errors }) = handler
+                .add_user_to_group(user, &group.id, GroupRole::Member, None)
+                .await
+            else {
+                panic!("{user:?} was added twice");
+            };
+            assert_eq!(errors["user"], ["already in group"]);
         }
 
-        Ok(users.into_iter().map(Option::unwrap).collect())
+        // The owner kept their role.
+        let role = sqlx::query_scalar!(
+            r#"select role as "role: GroupRole" from "user_groups" where group_id = $1 and user_id = $2"#,
+            to_sqlx_uuid(group.id),
+            to_sqlx_uuid(a.user_id),
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(role, GroupRole::Owner);
+    }
+
+    #[sqlx::test]
+    async fn members_get_a_ledger_entry_per_ordered_pair(db: Pool<Postgres>) {
+        let handler = Handler::new(db.clone(), ledger::Handler::new());
+        let owner = create_user(&db, "alice").await;
+        let group = handler
+            .create_group("trip".to_string(), "MYR".to_string(), owner)
+            .await
+            .unwrap();
+
+        // Mix joining one at a time with joining in a batch.
+        let bob = create_user(&db, "bob").await;
+        handler
+            .add_user_to_group(&bob, &group.id, GroupRole::Member, None)
+            .await
+            .unwrap();
+        let mut batch = vec![];
+        for username in ["carol", "dave", "erin"] {
+            batch.push(create_user(&db, username).await.user_id);
+        }
+        handler
+            .add_users_to_group(&batch, &group.id, GroupRole::Member)
+            .await
+            .unwrap();
+
+        let count_entries = || {
+            sqlx::query!(
+                r#"
+                select currency, count(*) as "count!"
+                from "ledgers"
+                where group_id = $1
+                group by currency
+                order by currency
+                "#,
+                to_sqlx_uuid(group.id),
+            )
+            .fetch_all(&db)
+        };
+
+        // 5 members make 5 * 4 = 20 directed entries, only in the group's currency.
+        let entries = count_entries().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].currency, "MYR");
+        assert_eq!(entries[0].count, 5 * 4);
+
+        // Another currency only gets entries for the pair that used it, in both directions.
+        let mut tx = db.begin().await.unwrap();
+        handler
+            .ledger_handler
+            .update_ledger_entries(
+                ledger::LedgerUpdate {
+                    group_id: group.id,
+                    payer_id: owner.user_id,
+                    payee_id: bob.user_id,
+                    amount: 10,
+                    currency: "USD",
+                    tx_id: uuid::Uuid::new_v4(),
+                },
+                &mut tx,
+            )
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let entries = count_entries().await.unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.currency.as_str(), e.count))
+                .collect::<Vec<_>>(),
+            [("MYR", 5 * 4), ("USD", 2)]
+        );
+    }
+
+    #[sqlx::test]
+    async fn leave_group_requires_being_settled_up(db: Pool<Postgres>) {
+        let handler = Handler::new(db.clone(), ledger::Handler::new());
+        let a = create_user(&db, "alice").await;
+        let b = create_user(&db, "bob").await;
+        let c = create_user(&db, "carol").await;
+
+        let group = handler
+            .create_group("trip".to_string(), "MYR".to_string(), a)
+            .await
+            .unwrap();
+        for user in [&b, &c] {
+            handler
+                .add_user_to_group(user, &group.id, GroupRole::Member, None)
+                .await
+                .unwrap();
+        }
+
+        // B owes A.
+        record(&handler, group.id, a, b).await;
+
+        let Err(Error::UnprocessableEntity { errors }) = handler.leave_group(&b, &group.id).await
+        else {
+            panic!("bob left while owing alice");
+        };
+        assert_eq!(
+            errors["balance"],
+            [format!("user owes 0.10 MYR to {}", a.user_id)]
+        );
+
+        // C has nothing outstanding with anyone.
+        handler.leave_group(&c, &group.id).await.unwrap();
+
+        let members = sqlx::query_scalar!(
+            r#"select user_id from "user_groups" where group_id = $1 order by user_id"#,
+            to_sqlx_uuid(group.id),
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        let mut expected = vec![to_sqlx_uuid(a.user_id), to_sqlx_uuid(b.user_id)];
+        expected.sort();
+        assert_eq!(members, expected);
+    }
+
+    #[sqlx::test]
+    async fn update_ledger_entries_moves_one_entry_each_way(db: Pool<Postgres>) {
+        let handler = Handler::new(db.clone(), ledger::Handler::new());
+        let a = create_user(&db, "alice").await;
+        let b = create_user(&db, "bob").await;
+        let c = create_user(&db, "carol").await;
+        let outsider = create_user(&db, "dave").await;
+
+        let group = handler
+            .create_group("trip".to_string(), "MYR".to_string(), a)
+            .await
+            .unwrap();
+        for user in [&b, &c] {
+            handler
+                .add_user_to_group(user, &group.id, GroupRole::Member, None)
+                .await
+                .unwrap();
+        }
+
+        record(&handler, group.id, a, b).await;
+
+        let entries = sqlx::query!(
+            r#"
+            select this_user, other_user, amount
+            from "ledgers"
+            where group_id = $1 and amount <> 0
+            order by amount
+            "#,
+            to_sqlx_uuid(group.id),
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|e| (to_uuid(e.this_user), to_uuid(e.other_user), e.amount))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [(b.user_id, a.user_id, -10), (a.user_id, b.user_id, 10)]
+        );
+
+        // A pair that isn't in the group has no ledger, so nothing is recorded.
+        let mut tx = db.begin().await.unwrap();
+        let result = handler
+            .ledger_handler
+            .update_ledger_entries(
+                ledger::LedgerUpdate {
+                    group_id: group.id,
+                    payer_id: a.user_id,
+                    payee_id: outsider.user_id,
+                    amount: 10,
+                    currency: "MYR",
+                    tx_id: uuid::Uuid::new_v4(),
+                },
+                &mut tx,
+            )
+            .await;
+        assert!(matches!(result, Err(Error::UnprocessableEntity { .. })));
+        drop(tx);
+
+        let events = sqlx::query_scalar!(
+            r#"select count(*) as "count!" from "ledger_events" where group_id = $1"#,
+            to_sqlx_uuid(group.id),
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(events, 2);
     }
 }
@@ -1,5 +1,6 @@
 use crate::{
-    commons::to_sqlx_uuid,
+    commons::{to_sqlx_uuid, to_uuid},
+    dto::money::Money,
     http::{Error, Result},
 };
 
@@ -7,64 +8,205 @@ use sqlx::{self, Postgres, Transaction};
 
 pub trait LedgerHandler {}
 
+#[derive(Default)]
 pub struct Handler {}
 
+// A change to the pairwise ledger of `payer_id` and `payee_id`, see
+// `Handler::update_ledger_entries`.
+pub struct LedgerUpdate<'a> {
+    pub group_id: uuid::Uuid,
+    pub payer_id: uuid::Uuid,
+    pub payee_id: uuid::Uuid,
+    pub amount: i64,
+    pub currency: &'a str,
+    // The transaction that caused the change, recorded in `ledger_events`.
+    pub tx_id: uuid::Uuid,
+}
+
+// A ledger entry of some user against `other_user`, see `Handler::lock_ledger_amounts`.
+pub struct LedgerAmount {
+    pub other_user: uuid::Uuid,
+    pub currency: String,
+    pub amount: i64,
+}
+
 impl Handler {
     pub fn new() -> Self {
         Self {}
     }
 
-    pub async fn init_ledger_entries(
+    // Inserts the missing zeroed entries in the group's currency of every pair of members of
+    // group `group_id` in both directions, and returns how many were missing.
+    //
+    // Existing entries are left alone, so this can be called after any change to the members
+    // of the group. A group of N members ends up with exactly N * (N - 1) entries in its
+    // currency, one per ordered pair. Entries in other currencies are created the first time a
+    // pair uses them, see `update_ledger_entries`, so that balances in different currencies are
+    // never summed up together.
+    pub async fn ensure_ledger_complete(
         &self,
         group_id: uuid::Uuid,
-        user_id: uuid::Uuid,
-        other_users_in_group_ids: Vec<uuid::Uuid>,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<u64, Error> {
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO "ledgers"
+              (group_id, this_user, other_user, currency)
+            SELECT $1, this.user_id, other.user_id, g.currency
+            FROM "user_groups" this
+            JOIN "user_groups" other
+              ON other.group_id = this.group_id AND other.user_id <> this.user_id
+            JOIN "groups" g ON g.id = this.group_id
+            WHERE this.group_id = $1
+            ON CONFLICT (group_id, this_user, other_user, currency) DO NOTHING
+        "#,
+            to_sqlx_uuid(group_id),
+        )
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+
+        log::debug!(
+            "[ensure_ledger_complete] inserted {inserted} ledger entries in group {group_id}"
+        );
+
+        Ok(inserted)
+    }
+
+    // Moves `amount` between `payer_id` and `payee_id` in both directions of their pairwise
+    // ledger in `currency`: the payer's side goes up by `amount` and the payee's side goes down
+    // by it.
+    //
+    // The entries are created if the pair hasn't used `currency` before, see
+    // `ensure_ledger_complete`. Both changes are recorded in `ledger_events` as caused by the
+    // transaction `tx_id`.
+    //
+    // Fails unless both users are members of the group.
+    pub async fn update_ledger_entries(
+        &self,
+        update: LedgerUpdate<'_>,
         tx: &mut Transaction<'_, Postgres>,
     ) -> Result<(), Error> {
-        log::debug!("other users: {:?}", other_users_in_group_ids);
+        let LedgerUpdate {
+            group_id,
+            payer_id,
+            payee_id,
+            amount,
+            currency,
+            tx_id,
+        } = update;
 
-        if other_users_in_group_ids.is_empty() {
-            return Ok(());
+        // Entries are only ever between members, even though they're created on first use.
+        let members = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) as "count!"
+            FROM "user_groups"
+            WHERE group_id = $1 AND user_id IN ($2, $3)
+        "#,
+            to_sqlx_uuid(group_id),
+            to_sqlx_uuid(payer_id),
+            to_sqlx_uuid(payee_id),
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        if members != 2 {
+            log::error!(
+                "[update_ledger_entries] no ledger between {payer_id} and {payee_id} in group {group_id}, they aren't both members"
+            );
+            return Err(Error::unprocessable_entity([(
+                "ledger",
+                "both users must be members of the group",
+            )]));
         }
 
-        //[...current_users, user_id * len(current_users)]
-        let left_side_ids = other_users_in_group_ids
-            .iter()
-            .chain(
-                [user_id]
-                    .iter()
-                    .cycle()
-                    .take(other_users_in_group_ids.len()),
-            )
-            .map(|id| to_sqlx_uuid(*id))
-            .collect::<Vec<_>>();
-
-        log::debug!("left: {:?}", left_side_ids);
-
-        //[user_id * len(current_users), ...current_users, ]
-        let right_side_ids = [user_id]
-            .iter()
-            .cycle()
-            .take(other_users_in_group_ids.len())
-            .chain(other_users_in_group_ids.iter())
-            .map(|id| to_sqlx_uuid(*id))
-            .collect::<Vec<_>>();
-
-        log::debug!("right: {:?}", right_side_ids);
-
         sqlx::query!(
             r#"
             INSERT INTO "ledgers"
-              (group_id, this_user, other_user)
-            VALUES (
-              $1,
-              unnest($2::uuid[]),
-              unnest($3::uuid[])
-            )
+              (group_id, this_user, other_user, currency, amount)
+            VALUES
+              ($1, $2, $3, $4, $5),
+              ($1, $3, $2, $4, -$5)
+            ON CONFLICT (group_id, this_user, other_user, currency)
+            DO UPDATE SET amount = "ledgers".amount + EXCLUDED.amount
+        "#,
+            to_sqlx_uuid(group_id),
+            to_sqlx_uuid(payer_id),
+            to_sqlx_uuid(payee_id),
+            currency,
+            amount,
+        )
+        .execute(&mut **tx)
+        .await
+        .map_err(map_overflow)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO "ledger_events"
+              (group_id, this_user, other_user, currency, delta, caused_by_tx_id)
+            VALUES
+              ($1, $2, $3, $4, $5, $6),
+              ($1, $3, $2, $4, -$5, $6)
+        "#,
+            to_sqlx_uuid(group_id),
+            to_sqlx_uuid(payer_id),
+            to_sqlx_uuid(payee_id),
+            currency,
+            amount,
+            to_sqlx_uuid(tx_id),
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    // Returns every ledger entry where `user_id` is `this_user` within the group, locking them
+    // until `tx` ends.
+    pub async fn lock_ledger_amounts(
+        &self,
+        group_id: uuid::Uuid,
+        user_id: uuid::Uuid,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<LedgerAmount>, Error> {
+        let amounts = sqlx::query!(
+            r#"
+            SELECT other_user, currency, amount
+            FROM "ledgers"
+            WHERE group_id = $1 AND this_user = $2
+            FOR UPDATE
+        "#,
+            to_sqlx_uuid(group_id),
+            to_sqlx_uuid(user_id),
+        )
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|row| LedgerAmount {
+            other_user: to_uuid(row.other_user),
+            currency: row.currency,
+            amount: row.amount,
+        })
+        .collect();
+
+        Ok(amounts)
+    }
+
+    // Deletes every ledger entry of `user_id` within the group, in both directions.
+    pub async fn delete_ledger_entries(
+        &self,
+        group_id: uuid::Uuid,
+        user_id: uuid::Uuid,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM "ledgers"
+            WHERE
+                group_id = $1 AND
+                (this_user = $2 OR other_user = $2)
         "#,
             to_sqlx_uuid(group_id),
-            &left_side_ids,
-            &right_side_ids,
+            to_sqlx_uuid(user_id),
         )
         .execute(&mut **tx)
         .await?;
@@ -74,3 +216,24 @@ impl Handler {
 }
 
 impl LedgerHandler for Handler {}
+
+// Adds `amount` to `total`, failing instead of overflowing.
+pub fn add_amount(total: &mut Money, amount: Money) -> Result<(), Error> {
+    *total = total.checked_add(amount).ok_or_else(|| {
+        Error::Anyhow(anyhow::anyhow!(
+            "ledger amount overflow adding {amount} to {total}"
+        ))
+    })?;
+
+    Ok(())
+}
+
+// Postgres rejects a ledger amount that no longer fits in a `bigint` with `numeric_value_out_of_range`.
+fn map_overflow(e: sqlx::Error) -> Error {
+    match e {
+        sqlx::Error::Database(dbe) if dbe.code().as_deref() == Some("22003") => {
+            Error::unprocessable_entity([("amount", "too large for the ledger")])
+        }
+        e => Error::Sqlx(e),
+    }
+}
@@ -0,0 +1,24 @@
+use async_trait::async_trait;
+
+/// Sends emails to users, e.g. password reset tokens.
+///
+/// This is a trait so that a real email service can be plugged in, or a fake one in tests.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> anyhow::Result<()>;
+}
+
+/// Doesn't send anything, only logs the emails.
+///
+/// Good enough for development, as there's no email integration yet.
+#[derive(Default)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> anyhow::Result<()> {
+        log::info!("[LogMailer] to: {to}, subject: {subject}\n{body}");
+
+        Ok(())
+    }
+}
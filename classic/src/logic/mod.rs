@@ -1,2 +1,10 @@
+pub mod avatar;
+pub mod currency;
 pub mod group;
 pub mod ledger;
+pub mod mailer;
+pub mod oauth;
+pub mod rate_limit;
+pub mod settlement;
+pub mod split;
+pub mod totp;
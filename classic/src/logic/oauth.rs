@@ -0,0 +1,231 @@
+use async_trait::async_trait;
+use axum::body::HttpBody;
+use hyper::{client::HttpConnector, header::CACHE_CONTROL, Client};
+use hyper_tls::HttpsConnector;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use tokio::sync::RwLock;
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The values Google puts in the `iss` claim of its ID tokens.
+const GOOGLE_ISSUERS: &[&str] = &["accounts.google.com", "https://accounts.google.com"];
+
+/// How long to keep Google's keys if it doesn't say, via `Cache-Control: max-age`.
+const DEFAULT_KEYS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Keys are fetched again when a token is signed with one we don't know, e.g. after Google
+/// rotated them, but at most this often so that made up key ids can't make us hammer Google.
+const MIN_KEYS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who an ID token says its bearer is.
+#[derive(Debug)]
+pub struct GoogleIdentity {
+    /// Google's id for the account, which unlike the email never changes.
+    pub subject: String,
+    pub email: String,
+    /// Whether Google checked that the account owns `email`.
+    pub email_verified: bool,
+}
+
+/// Checks ID tokens issued by "Sign in with Google".
+///
+/// This is a trait so that it can be stubbed in tests, like `AvatarProvider`.
+#[async_trait]
+pub trait IdTokenVerifier: Send + Sync {
+    /// Whether we accept ID tokens at all, i.e. whether any client ids are configured.
+    fn enabled(&self) -> bool;
+
+    /// Returns who `id_token` belongs to, or `None` if it isn't a valid token for one of our
+    /// client ids. Errors are reserved for failing to get Google's keys.
+    async fn verify(&self, id_token: &str) -> anyhow::Result<Option<GoogleIdentity>>;
+}
+
+/// Verifies ID tokens against the keys published at `jwks_url`, caching them for as long as
+/// Google allows.
+pub struct GoogleIdTokenVerifier {
+    client: Client<HttpsConnector<HttpConnector>>,
+    jwks_url: String,
+    client_ids: Vec<String>,
+    keys: RwLock<Option<CachedKeys>>,
+}
+
+struct CachedKeys {
+    by_kid: HashMap<String, DecodingKey>,
+    fetched_at: Instant,
+    max_age: Duration,
+}
+
+#[derive(serde::Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(serde::Deserialize)]
+struct Jwk {
+    kid: String,
+    kty: String,
+    n: String,
+    e: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Claims {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+impl GoogleIdTokenVerifier {
+    pub fn new(jwks_url: String, client_ids: Vec<String>) -> Self {
+        Self {
+            client: Client::builder().build(HttpsConnector::new()),
+            jwks_url,
+            client_ids,
+            keys: RwLock::new(None),
+        }
+    }
+
+    /// The key with id `kid`, fetching the keys again if they're stale or don't include it.
+    async fn key(&self, kid: &str) -> anyhow::Result<Option<DecodingKey>> {
+        if let Some(cached) = &*self.keys.read().await {
+            let age = cached.fetched_at.elapsed();
+            if age < cached.max_age {
+                if let Some(key) = cached.by_kid.get(kid) {
+                    return Ok(Some(key.clone()));
+                }
+                if age < MIN_KEYS_REFRESH_INTERVAL {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let mut keys = self.keys.write().await;
+        // Another request may have fetched them while we waited for the lock.
+        let fresh = keys
+            .as_ref()
+            .is_some_and(|cached| cached.fetched_at.elapsed() < MIN_KEYS_REFRESH_INTERVAL);
+        if !fresh {
+            *keys = Some(self.fetch_keys().await?);
+        }
+
+        Ok(keys
+            .as_ref()
+            .and_then(|cached| cached.by_kid.get(kid).cloned()))
+    }
+
+    async fn fetch_keys(&self) -> anyhow::Result<CachedKeys> {
+        let uri = self
+            .jwks_url
+            .parse()
+            .map_err(|e| anyhow::anyhow!("failed to parse jwks url {}: {e}", self.jwks_url))?;
+
+        let mut res = self
+            .client
+            .get(uri)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to get google keys: {e}"))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            anyhow::bail!("google keys endpoint returned {status}");
+        }
+
+        let max_age = res
+            .headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_max_age)
+            .unwrap_or(DEFAULT_KEYS_MAX_AGE);
+
+        let mut full_body: Vec<u8> = Vec::new();
+        while let Some(chunk) = res.body_mut().data().await {
+            let chunk = chunk.map_err(|e| anyhow::anyhow!("failed to read google keys: {e}"))?;
+            full_body.extend_from_slice(&chunk);
+        }
+
+        let jwks: JwkSet = serde_json::from_slice(&full_body)
+            .map_err(|e| anyhow::anyhow!("failed to parse google keys: {e}"))?;
+
+        let mut by_kid = HashMap::new();
+        for jwk in jwks.keys.into_iter().filter(|jwk| jwk.kty == "RSA") {
+            match DecodingKey::from_rsa_components(&jwk.n, &jwk.e) {
+                Ok(key) => {
+                    by_kid.insert(jwk.kid, key);
+                }
+                Err(e) => log::warn!("[fetch_keys] skipping invalid google key {}: {e}", jwk.kid),
+            }
+        }
+        log::debug!(
+            "[fetch_keys] got {} google keys, valid for {max_age:?}",
+            by_kid.len()
+        );
+
+        Ok(CachedKeys {
+            by_kid,
+            fetched_at: Instant::now(),
+            max_age,
+        })
+    }
+}
+
+#[async_trait]
+impl IdTokenVerifier for GoogleIdTokenVerifier {
+    fn enabled(&self) -> bool {
+        !self.client_ids.is_empty()
+    }
+
+    async fn verify(&self, id_token: &str) -> anyhow::Result<Option<GoogleIdentity>> {
+        let header = match jsonwebtoken::decode_header(id_token) {
+            Ok(header) => header,
+            Err(e) => {
+                log::info!("[verify] malformed google id token: {e}");
+                return Ok(None);
+            }
+        };
+        if header.alg != Algorithm::RS256 {
+            log::info!("[verify] google id token signed with {:?}", header.alg);
+            return Ok(None);
+        }
+        let Some(key) = self.key(header.kid.as_deref().unwrap_or_default()).await? else {
+            log::info!(
+                "[verify] google id token signed with unknown key {:?}",
+                header.kid
+            );
+            return Ok(None);
+        };
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&self.client_ids);
+        validation.set_issuer(GOOGLE_ISSUERS);
+
+        let claims = match jsonwebtoken::decode::<Claims>(id_token, &key, &validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                log::info!("[verify] invalid google id token: {e}");
+                return Ok(None);
+            }
+        };
+        let Some(email) = claims.email else {
+            log::info!("[verify] google id token for {} has no email", claims.sub);
+            return Ok(None);
+        };
+
+        Ok(Some(GoogleIdentity {
+            subject: claims.sub,
+            email,
+            email_verified: claims.email_verified,
+        }))
+    }
+}
+
+/// The `max-age` directive of a `Cache-Control` header.
+fn parse_max_age(cache_control: &str) -> Option<Duration> {
+    cache_control.split(',').find_map(|directive| {
+        let seconds = directive.trim().strip_prefix("max-age=")?;
+        seconds.parse().ok().map(Duration::from_secs)
+    })
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Once this many keys are tracked, stale ones are dropped whenever a failure is recorded, so
+/// that the map can't grow without bound.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Locks out keys, e.g. an email or IP address, after too many failed attempts at something.
+///
+/// This is a trait so that the counts could be shared between instances of the API, e.g. in the
+/// database, if per-instance counts turn out not to be enough.
+pub trait RateLimiter: Send + Sync {
+    /// How long until `key` may try again, if it's locked out.
+    fn locked_out(&self, key: &str) -> Option<Duration>;
+
+    /// Count a failed attempt for `key`, locking it out once it fails `max_failures` times
+    /// within the window.
+    fn record_failure(&self, key: &str, max_failures: u32);
+
+    /// Forget the failed attempts for `key`, e.g. after a successful one.
+    fn reset(&self, key: &str);
+}
+
+/// Counts failures in memory, so each instance of the API counts separately and the counts
+/// are lost on restart.
+pub struct InMemoryRateLimiter {
+    /// Failures older than this don't count anymore.
+    window: Duration,
+    /// How long a key stays locked out.
+    lockout: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+struct Entry {
+    failures: u32,
+    window_started: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Entry {
+    fn is_stale(&self, now: Instant, window: Duration) -> bool {
+        self.locked_until.is_none_or(|until| until <= now)
+            && now.duration_since(self.window_started) >= window
+    }
+}
+
+impl InMemoryRateLimiter {
+    pub fn new(window: Duration, lockout: Duration) -> Self {
+        Self {
+            window,
+            lockout,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl RateLimiter for InMemoryRateLimiter {
+    fn locked_out(&self, key: &str) -> Option<Duration> {
+        let entries = self.entries.lock().unwrap();
+        let until = entries.get(key)?.locked_until?;
+
+        until.checked_duration_since(Instant::now())
+    }
+
+    fn record_failure(&self, key: &str, max_failures: u32) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= PRUNE_THRESHOLD {
+            entries.retain(|_, entry| !entry.is_stale(now, self.window));
+        }
+
+        let entry = entries.entry(key.to_string()).or_insert(Entry {
+            failures: 0,
+            window_started: now,
+            locked_until: None,
+        });
+
+        // Start counting afresh once the previous window or lockout is over.
+        if entry.is_stale(now, self.window) {
+            *entry = Entry {
+                failures: 0,
+                window_started: now,
+                locked_until: None,
+            };
+        }
+
+        entry.failures += 1;
+        if entry.failures >= max_failures {
+            entry.locked_until = Some(now + self.lockout);
+        }
+    }
+
+    fn reset(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
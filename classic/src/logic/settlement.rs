@@ -0,0 +1,164 @@
+use crate::dto::{ledger::Settlement, money::Money};
+
+// Computes a small set of payments that zeroes out every user's net balance, using a greedy
+// min-cash-flow: repeatedly let the biggest debtor pay the biggest creditor as much as
+// possible.
+//
+// `balances` are per-user net balances, where positive means the user is owed money and
+// negative means they owe. Amounts are integers so no rounding is involved, and the
+// settlements always sum up to exactly the total of the positive balances as long as the
+// balances sum to zero, which they do for balances derived from the ledger.
+pub fn simplify_debts(balances: Vec<(uuid::Uuid, i64)>) -> Vec<Settlement> {
+    // Sort by user id first so that ties are always broken the same way.
+    let mut balances = balances
+        .into_iter()
+        .filter(|(_, amount)| *amount != 0)
+        .collect::<Vec<_>>();
+    balances.sort_by_key(|(id, _)| *id);
+
+    let mut settlements = vec![];
+
+    loop {
+        let creditor = balances
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, amount))| *amount > 0)
+            .max_by_key(|(i, (_, amount))| (*amount, std::cmp::Reverse(*i)))
+            .map(|(i, _)| i);
+        let debtor = balances
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, amount))| *amount < 0)
+            .min_by_key(|(i, (_, amount))| (*amount, *i))
+            .map(|(i, _)| i);
+
+        let (Some(creditor), Some(debtor)) = (creditor, debtor) else {
+            break;
+        };
+
+        let amount = balances[creditor].1.min(-balances[debtor].1);
+        balances[creditor].1 -= amount;
+        balances[debtor].1 += amount;
+
+        settlements.push(Settlement {
+            from: balances[debtor].0,
+            to: balances[creditor].0,
+            amount: Money::from_minor(amount),
+        });
+    }
+
+    settlements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn users(n: u128) -> Vec<uuid::Uuid> {
+        (1..=n).map(uuid::Uuid::from_u128).collect()
+    }
+
+    // Net balances from a list of debts, each `(debtor, creditor, amount)`.
+    fn net(debts: &[(uuid::Uuid, uuid::Uuid, i64)]) -> Vec<(uuid::Uuid, i64)> {
+        let mut balances = HashMap::new();
+        for &(debtor, creditor, amount) in debts {
+            *balances.entry(debtor).or_insert(0) -= amount;
+            *balances.entry(creditor).or_insert(0) += amount;
+        }
+        balances.into_iter().collect()
+    }
+
+    // Checks that making every payment zeroes out every balance.
+    fn assert_settles(balances: Vec<(uuid::Uuid, i64)>, settlements: &[Settlement]) {
+        let mut balances = balances.into_iter().collect::<HashMap<_, _>>();
+        for settlement in settlements {
+            assert!(settlement.amount.minor() > 0, "{settlement:?}");
+            *balances.get_mut(&settlement.from).unwrap() += settlement.amount.minor();
+            *balances.get_mut(&settlement.to).unwrap() -= settlement.amount.minor();
+        }
+        assert!(balances.values().all(|amount| *amount == 0), "{balances:?}");
+    }
+
+    #[test]
+    fn three_person_cycle() {
+        let [a, b, c] = users(3)[..] else {
+            unreachable!()
+        };
+        // a owes b 30, b owes c 20 and c owes a 10, so a only has to pay b and c 10 each.
+        let balances = net(&[(a, b, 30), (b, c, 20), (c, a, 10)]);
+
+        let settlements = simplify_debts(balances.clone());
+
+        assert_eq!(
+            settlements,
+            [
+                Settlement {
+                    from: a,
+                    to: b,
+                    amount: Money::from_minor(10),
+                },
+                Settlement {
+                    from: a,
+                    to: c,
+                    amount: Money::from_minor(10),
+                },
+            ]
+        );
+        assert_settles(balances, &settlements);
+    }
+
+    #[test]
+    fn four_person_cycle() {
+        let [a, b, c, d] = users(4)[..] else {
+            unreachable!()
+        };
+        let balances = net(&[(a, b, 40), (b, c, 30), (c, d, 20), (d, a, 10)]);
+
+        let settlements = simplify_debts(balances.clone());
+
+        // Three payments at most, one fewer than the debts in the cycle.
+        assert_eq!(settlements.len(), 3);
+        assert_settles(balances, &settlements);
+    }
+
+    #[test]
+    fn already_settled() {
+        let [a, b, c] = users(3)[..] else {
+            unreachable!()
+        };
+        let balances = net(&[(a, b, 10), (b, c, 10), (c, a, 10)]);
+        assert!(balances.iter().all(|(_, amount)| *amount == 0));
+
+        assert_eq!(simplify_debts(balances), []);
+    }
+
+    #[test]
+    fn payments_sum_to_net_balances() {
+        let ids = users(5);
+        let balances = vec![
+            (ids[0], 700),
+            (ids[1], -250),
+            (ids[2], -199),
+            (ids[3], 1),
+            (ids[4], -252),
+        ];
+
+        let settlements = simplify_debts(balances.clone());
+
+        for (id, balance) in &balances {
+            let paid: i64 = settlements
+                .iter()
+                .filter(|s| s.from == *id)
+                .map(|s| s.amount.minor())
+                .sum();
+            let received: i64 = settlements
+                .iter()
+                .filter(|s| s.to == *id)
+                .map(|s| s.amount.minor())
+                .sum();
+            assert_eq!(received - paid, *balance, "user {id}");
+        }
+        assert_settles(balances, &settlements);
+    }
+}
@@ -0,0 +1,46 @@
+// Splits `amount` equally into `count` parts.
+//
+// Amounts are integers, so the remainder of the division is handed out one unit at a time
+// to the first parts. The parts always sum up to exactly `amount`.
+pub fn equal_split(amount: i64, count: usize) -> Vec<i64> {
+    if count == 0 {
+        return vec![];
+    }
+
+    let count = count as i64;
+    let share = amount / count;
+    let remainder = amount % count;
+
+    (0..count)
+        .map(|i| if i < remainder { share + 1 } else { share })
+        .collect()
+}
+
+// Splits `amount` into parts proportional to `weights`, e.g. percentages or shares.
+//
+// Each part is rounded down, and the units lost to rounding are then handed out one at a
+// time in the order given by `remainder_order` (indices into `weights`). The parts always
+// sum up to exactly `amount` as long as the weights sum to a positive number.
+pub fn weighted_split(amount: i64, weights: &[i64], remainder_order: &[usize]) -> Vec<i64> {
+    let total_weight: i64 = weights.iter().sum();
+    if total_weight <= 0 {
+        return vec![0; weights.len()];
+    }
+
+    // Multiply in `i128` so large amounts can't overflow.
+    let mut parts = weights
+        .iter()
+        .map(|w| (amount as i128 * *w as i128 / total_weight as i128) as i64)
+        .collect::<Vec<_>>();
+
+    let mut remainder = amount - parts.iter().sum::<i64>();
+    for i in remainder_order.iter().cycle() {
+        if remainder <= 0 {
+            break;
+        }
+        parts[*i] += 1;
+        remainder -= 1;
+    }
+
+    parts
+}
@@ -0,0 +1,151 @@
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
+use anyhow::anyhow;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, TOTP};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Shown as the account's issuer in authenticator apps.
+const ISSUER: &str = "Splitje";
+
+/// The size of a generated secret, 160 bits as recommended by RFC 4226.
+const SECRET_BYTES: usize = 20;
+
+/// AES-GCM's standard nonce size.
+const NONCE_BYTES: usize = 12;
+
+/// Codes from this many 30 second steps before or after the current one are accepted too,
+/// to allow for clock drift and the time it takes to type a code in.
+const SKEW_STEPS: u8 = 1;
+
+/// Generate a new random TOTP secret.
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// The `otpauth://` URI to add the secret to an authenticator app with, usually shown as a QR code.
+pub fn provisioning_uri(secret: &[u8], account_name: &str) -> anyhow::Result<String> {
+    Ok(totp(secret, account_name)?.get_url())
+}
+
+/// Whether `code` is valid for `secret` right now, give or take `SKEW_STEPS`.
+pub fn verify(secret: &[u8], code: &str) -> anyhow::Result<bool> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| anyhow!("system clock is before the unix epoch: {e}"))?;
+
+    verify_at(secret, code, now.as_secs())
+}
+
+/// Like `verify`, but at `time` seconds since the unix epoch instead of right now.
+fn verify_at(secret: &[u8], code: &str, time: u64) -> anyhow::Result<bool> {
+    // The account name only matters for `provisioning_uri`.
+    Ok(totp(secret, "")?.check(code.trim(), time))
+}
+
+fn totp(secret: &[u8], account_name: &str) -> anyhow::Result<TOTP> {
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        SKEW_STEPS,
+        30,
+        secret.to_vec(),
+        Some(ISSUER.to_string()),
+        account_name.to_string(),
+    )
+    .map_err(|e| anyhow!("invalid TOTP parameters: {e:?}"))
+}
+
+/// Encrypt a secret for storage with a key derived from `key`, see `Config::totp_encryption_key`.
+///
+/// The random nonce is prepended to the ciphertext.
+pub fn encrypt_secret(key: &str, secret: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_BYTES];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = cipher(key)?
+        .encrypt(Nonce::from_slice(&nonce), secret)
+        .map_err(|e| anyhow!("failed to encrypt TOTP secret: {e}"))?;
+
+    Ok([&nonce[..], &ciphertext].concat())
+}
+
+/// The reverse of `encrypt_secret`.
+pub fn decrypt_secret(key: &str, encrypted: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if encrypted.len() < NONCE_BYTES {
+        anyhow::bail!("encrypted TOTP secret is too short");
+    }
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_BYTES);
+
+    cipher(key)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| anyhow!("failed to decrypt TOTP secret: {e}"))
+}
+
+fn cipher(key: &str) -> anyhow::Result<Aes256Gcm> {
+    Aes256Gcm::new_from_slice(&Sha256::digest(key.as_bytes()))
+        .map_err(|e| anyhow!("invalid TOTP encryption key: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Halfway through a 30 second step, so that no offset below lands on a boundary.
+    const NOW: u64 = 1_700_000_025;
+
+    /// The code an authenticator app shows `steps` steps away from `NOW`.
+    fn code(secret: &[u8], steps: i64) -> String {
+        TOTP::new_unchecked(
+            Algorithm::SHA1,
+            6,
+            0,
+            30,
+            secret.to_vec(),
+            None,
+            String::new(),
+        )
+        .generate(NOW.checked_add_signed(steps * 30).unwrap())
+    }
+
+    #[test]
+    fn accepts_codes_within_skew() {
+        let secret = generate_secret();
+        let skew = i64::from(SKEW_STEPS);
+
+        for steps in -skew - 1..=skew + 1 {
+            assert_eq!(
+                verify_at(&secret, &code(&secret, steps), NOW).unwrap(),
+                steps.abs() <= skew,
+                "code {steps} steps away"
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_surrounding_whitespace() {
+        let secret = generate_secret();
+
+        assert!(verify_at(&secret, &format!(" {} ", code(&secret, 0)), NOW).unwrap());
+    }
+
+    #[test]
+    fn secret_round_trips() {
+        let secret = generate_secret();
+        let encrypted = encrypt_secret("key", &secret).unwrap();
+
+        assert_ne!(&encrypted[NONCE_BYTES..], &secret[..]);
+        assert_eq!(decrypt_secret("key", &encrypted).unwrap(), secret);
+    }
+
+    #[test]
+    fn decrypting_with_another_key_fails() {
+        let encrypted = encrypt_secret("key", &generate_secret()).unwrap();
+
+        assert!(decrypt_secret("another key", &encrypted).is_err());
+        assert!(decrypt_secret("key", &encrypted[..NONCE_BYTES - 1]).is_err());
+    }
+}
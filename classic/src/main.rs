@@ -4,15 +4,33 @@ use anyhow::Context;
 use clap::Parser;
 use sqlx::postgres::PgPoolOptions;
 
+use std::time::Duration;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
-    env_logger::init();
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
 
     let config = Config::parse();
 
+    // `PgPoolOptions` panics rather than returning an error for these.
+    if config.db_max_connections == 0 {
+        anyhow::bail!("DB_MAX_CONNECTIONS must be at least 1");
+    }
+    if config.db_min_connections > config.db_max_connections {
+        anyhow::bail!(
+            "DB_MIN_CONNECTIONS ({}) must not be greater than DB_MAX_CONNECTIONS ({})",
+            config.db_min_connections,
+            config.db_max_connections,
+        );
+    }
+
     let db = PgPoolOptions::new()
-        .max_connections(50)
+        .max_connections(config.db_max_connections)
+        .min_connections(config.db_min_connections)
+        .acquire_timeout(Duration::from_secs(config.db_acquire_timeout_seconds))
         .connect(&config.database_url)
         .await
         .context("could not connect to database_url")?;